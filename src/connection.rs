@@ -0,0 +1,330 @@
+use std::fs::File;
+use std::future::Future;
+use std::io::{Error, ErrorKind, Result};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, BufReader, BufWriter, ReadBuf};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::time::{sleep, Instant, Sleep};
+
+/// Stream a client is connected through.
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send {
+    /// Copies up to `count` bytes of `file`, starting at `offset`, straight
+    /// into the connection without passing them through user space, and
+    /// advances `offset` past what was sent. Resolves to `None` when the
+    /// connection can't do that and the caller has to copy by itself.
+    fn poll_send_file(
+        &mut self,
+        _cx: &mut Context<'_>,
+        _file: &File,
+        _offset: &mut u64,
+        _count: usize,
+    ) -> Poll<Option<Result<usize>>> {
+        Poll::Ready(None)
+    }
+}
+
+macro_rules! impl_socket_connection {
+    ($stream:ty) => {
+        impl Connection for $stream {
+            #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+            fn poll_send_file(
+                &mut self,
+                cx: &mut Context<'_>,
+                file: &File,
+                offset: &mut u64,
+                count: usize,
+            ) -> Poll<Option<Result<usize>>> {
+                use std::io::ErrorKind;
+                use std::os::fd::AsRawFd;
+                use tokio::io::Interest;
+
+                loop {
+                    if let Err(e) = ready!(self.poll_write_ready(cx)) {
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    match self.try_io(Interest::WRITABLE, || {
+                        sendfile::send(self.as_raw_fd(), file, offset, count)
+                    }) {
+                        // Readiness was stale, wait for the next one.
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+                        result => return Poll::Ready(Some(result)),
+                    }
+                }
+            }
+        }
+    };
+}
+
+impl_socket_connection!(TcpStream);
+impl_socket_connection!(UnixStream);
+
+impl<C: Connection> Connection for BufReader<C> {
+    fn poll_send_file(
+        &mut self,
+        cx: &mut Context<'_>,
+        file: &File,
+        offset: &mut u64,
+        count: usize,
+    ) -> Poll<Option<Result<usize>>> {
+        self.get_mut().poll_send_file(cx, file, offset, count)
+    }
+}
+
+impl<C: Connection> Connection for BufWriter<C> {
+    fn poll_send_file(
+        &mut self,
+        cx: &mut Context<'_>,
+        file: &File,
+        offset: &mut u64,
+        count: usize,
+    ) -> Poll<Option<Result<usize>>> {
+        // Whatever is buffered has to go out before the file does.
+        if let Err(e) = ready!(Pin::new(&mut *self).poll_flush(cx)) {
+            return Poll::Ready(Some(Err(e)));
+        }
+        self.get_mut().poll_send_file(cx, file, offset, count)
+    }
+}
+
+/// Write buffer that is flushed before every read from the connection. The
+/// read might have to wait for the client, who may in turn be waiting for
+/// the responses still in the buffer.
+pub struct FlushBeforeRead<C> {
+    inner: BufWriter<C>,
+}
+
+impl<C: Connection> FlushBeforeRead<C> {
+    pub fn new(stream: C) -> Self {
+        FlushBeforeRead {
+            inner: BufWriter::new(stream),
+        }
+    }
+}
+
+impl<C: Connection> AsyncRead for FlushBeforeRead<C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let inner = &mut self.get_mut().inner;
+        if !inner.buffer().is_empty() {
+            ready!(Pin::new(&mut *inner).poll_flush(cx))?;
+        }
+        Pin::new(inner).poll_read(cx, buf)
+    }
+}
+
+impl<C: Connection> AsyncWrite for FlushBeforeRead<C> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<C: Connection> Connection for FlushBeforeRead<C> {
+    fn poll_send_file(
+        &mut self,
+        cx: &mut Context<'_>,
+        file: &File,
+        offset: &mut u64,
+        count: usize,
+    ) -> Poll<Option<Result<usize>>> {
+        self.inner.poll_send_file(cx, file, offset, count)
+    }
+}
+
+/// Deadline that only runs while the connection makes no progress, so a
+/// slow peer can take as long as it needs as long as it keeps going.
+struct Stall {
+    timeout: Duration,
+    deadline: Pin<Box<Sleep>>,
+    /// Whether the deadline is running, i.e. the last call had to wait.
+    waiting: bool,
+    message: &'static str,
+}
+
+impl Stall {
+    fn new(timeout: Duration, message: &'static str) -> Self {
+        Stall {
+            timeout,
+            deadline: Box::pin(sleep(timeout)),
+            waiting: false,
+            message,
+        }
+    }
+
+    /// Starts the deadline when a call has to wait and stops it once one
+    /// goes through.
+    fn check<T>(&mut self, cx: &mut Context<'_>, poll: Poll<T>) -> Poll<Result<T>> {
+        if let Poll::Ready(result) = poll {
+            self.waiting = false;
+            return Poll::Ready(Ok(result));
+        }
+        if !self.waiting {
+            self.waiting = true;
+            self.deadline.as_mut().reset(Instant::now() + self.timeout);
+        }
+        ready!(self.deadline.as_mut().poll(cx));
+        Poll::Ready(Err(Error::new(ErrorKind::TimedOut, self.message)))
+    }
+}
+
+/// Fails a write once the connection has taken nothing for `timeout`. A
+/// client that keeps reading can take as long as it needs for the whole
+/// response.
+pub struct WriteTimeout<'a, C> {
+    inner: &'a mut C,
+    stall: Stall,
+}
+
+impl<'a, C: Connection> WriteTimeout<'a, C> {
+    pub fn new(inner: &'a mut C, timeout: Duration) -> Self {
+        WriteTimeout {
+            inner,
+            stall: Stall::new(timeout, "timed out writing response"),
+        }
+    }
+}
+
+/// Fails a read once the connection has sent nothing for `timeout`. A client
+/// that keeps sending can take as long as it needs for the whole body.
+pub struct ReadTimeout<'a, R> {
+    inner: &'a mut R,
+    stall: Stall,
+}
+
+impl<'a, R: AsyncBufRead + Unpin> ReadTimeout<'a, R> {
+    pub fn new(inner: &'a mut R, timeout: Duration) -> Self {
+        ReadTimeout {
+            inner,
+            stall: Stall::new(timeout, "timed out reading request"),
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncRead for ReadTimeout<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut *this.inner).poll_read(cx, buf);
+        this.stall.check(cx, poll)?
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for ReadTimeout<'_, R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut *this.inner).poll_fill_buf(cx);
+        match this.stall.check(cx, poll) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn consume(self: Pin<&mut Self>, amount: usize) {
+        Pin::new(&mut *self.get_mut().inner).consume(amount)
+    }
+}
+
+impl<C: Connection> AsyncRead for WriteTimeout<'_, C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        Pin::new(&mut *self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<C: Connection> AsyncWrite for WriteTimeout<'_, C> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut *this.inner).poll_write(cx, buf);
+        this.stall.check(cx, poll)?
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut *this.inner).poll_flush(cx);
+        this.stall.check(cx, poll)?
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut *this.inner).poll_shutdown(cx);
+        this.stall.check(cx, poll)?
+    }
+}
+
+impl<C: Connection> Connection for WriteTimeout<'_, C> {
+    fn poll_send_file(
+        &mut self,
+        cx: &mut Context<'_>,
+        file: &File,
+        offset: &mut u64,
+        count: usize,
+    ) -> Poll<Option<Result<usize>>> {
+        let poll = self.inner.poll_send_file(cx, file, offset, count);
+        match self.stall.check(cx, poll) {
+            Poll::Ready(Ok(sent)) => Poll::Ready(sent),
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Reads `count` bytes of `file` at `offset` into the page cache. Handing
+/// them to a socket straight after then finds them there rather than waiting
+/// for the disk. Only a hint, failing is fine.
+pub fn prefetch(file: &File, offset: u64, count: usize) {
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    sendfile::prefetch(file, offset, count);
+    #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+    let _ = (file, offset, count);
+}
+
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+mod sendfile {
+    use std::fs::File;
+    use std::io::{Error, Result};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::raw::c_int;
+
+    extern "C" {
+        fn sendfile(out_fd: c_int, in_fd: c_int, offset: *mut i64, count: usize) -> isize;
+        fn readahead(fd: c_int, offset: i64, count: usize) -> isize;
+    }
+
+    /// Blocks until the range is read into the page cache.
+    pub fn prefetch(file: &File, offset: u64, count: usize) {
+        // SAFETY: the descriptor is open for the duration of the call.
+        unsafe { readahead(file.as_raw_fd(), offset as i64, count) };
+    }
+
+    pub fn send(socket: RawFd, file: &File, offset: &mut u64, count: usize) -> Result<usize> {
+        let mut file_offset = *offset as i64;
+        // SAFETY: both descriptors are open for the duration of the call and
+        // `file_offset` outlives it.
+        let sent = unsafe { sendfile(socket, file.as_raw_fd(), &mut file_offset, count) };
+        if sent < 0 {
+            return Err(Error::last_os_error());
+        }
+        *offset = file_offset as u64;
+        Ok(sent as usize)
+    }
+}
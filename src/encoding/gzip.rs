@@ -1,9 +1,16 @@
 use std::io::{Read, Result, Write};
+use std::mem;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
 
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use tokio::io::{AsyncRead, ReadBuf};
 
 use super::types::{ContentEncoding, EncodedContent};
 
+/// Size of the buffer each piece of input is read into.
+const INPUT_SIZE: usize = 16 * 1024;
+
 pub fn encode(content: EncodedContent) -> Result<EncodedContent> {
     let buffer = content.buffer;
     let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
@@ -23,3 +30,89 @@ pub fn decode(content: EncodedContent) -> Result<EncodedContent> {
         buffer: bytes_result,
     })
 }
+
+/// Gzips what `inner` yields while it is read, so a body can be compressed
+/// without holding it in memory as a whole.
+pub struct GzipReader<R> {
+    inner: R,
+    /// Gone once `inner` has ended and the trailer is out.
+    encoder: Option<GzEncoder<Vec<u8>>>,
+    output: Vec<u8>,
+    position: usize,
+}
+
+impl<R: AsyncRead + Unpin> GzipReader<R> {
+    pub fn new(inner: R) -> Self {
+        GzipReader {
+            inner,
+            encoder: Some(GzEncoder::new(Vec::new(), Compression::default())),
+            output: vec![],
+            position: 0,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for GzipReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.position < this.output.len() {
+                let count = buf.remaining().min(this.output.len() - this.position);
+                buf.put_slice(&this.output[this.position..this.position + count]);
+                this.position += count;
+                return Poll::Ready(Ok(()));
+            }
+            let Some(encoder) = this.encoder.as_mut() else {
+                return Poll::Ready(Ok(()));
+            };
+
+            // The encoder holds back what it can't compress yet, so reading
+            // more may produce nothing and has to be repeated.
+            let mut input = [0u8; INPUT_SIZE];
+            let mut input = ReadBuf::new(&mut input);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut input))?;
+            this.output = if input.filled().is_empty() {
+                this.encoder.take().unwrap().finish()?
+            } else {
+                encoder.write_all(input.filled())?;
+                mem::take(encoder.get_mut())
+            };
+            this.position = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn gzip_reader_output_decodes_to_its_input() {
+        let input: Vec<u8> = (0..100_000u32).flat_map(|n| n.to_le_bytes()).collect();
+        let mut compressed = vec![];
+        GzipReader::new(input.as_slice())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+
+        let decoded = decode(EncodedContent {
+            buffer: compressed,
+            encoding_type: ContentEncoding::Gzip,
+        })
+        .unwrap();
+        assert_eq!(decoded.buffer, input);
+    }
+
+    #[tokio::test]
+    async fn gzip_reader_of_nothing_is_still_a_gzip_stream() {
+        let mut compressed = vec![];
+        GzipReader::new(&b""[..]).read_to_end(&mut compressed).await.unwrap();
+        assert!(compressed.starts_with(&[0x1f, 0x8b]));
+    }
+}
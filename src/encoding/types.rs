@@ -1,3 +1,5 @@
+#![allow(clippy::needless_arbitrary_self_type)]
+
 use crate::encoding::gzip;
 
 use std::io::Result;
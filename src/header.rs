@@ -0,0 +1,193 @@
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+
+/// Header fields with case-insensitive names. A name may appear several times,
+/// fields keep the order they were added in.
+#[derive(Debug, Default, Clone)]
+pub struct HeaderMap {
+    fields: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap::default()
+    }
+
+    /// Adds a field, keeping any existing ones with the same name.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.fields.push((String::from(name), String::from(value)));
+    }
+
+    /// Replaces every field named `name` with a single one.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append(name, value);
+    }
+
+    /// Drops every field named `name`.
+    pub fn remove(&mut self, name: &str) {
+        self.fields
+            .retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+    }
+
+    /// First value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of `name`, in the order they were added.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of `name` joined into one comma separated list, the way
+    /// repeated list-valued fields are meant to be read.
+    pub fn get_joined(&self, name: &str) -> Option<String> {
+        let values: Vec<&str> = self.get_all(name).collect();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+impl fmt::Display for HeaderMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (name, value) in self.iter() {
+            write!(f, "{}: {:?}, ", name, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Checks that a field can be written out as is: the name has to be a token
+/// and the value must not be able to start a new line.
+pub fn validate(name: &str, value: &str) -> Result<()> {
+    if !is_token(name) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid header name: {:?}", name),
+        ));
+    }
+    if value.bytes().any(|byte| matches!(byte, b'\r' | b'\n' | b'\0')) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid value for header {}: {:?}", name, value),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `text` is a token, the grammar of header names and methods.
+pub fn is_token(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(is_token_char)
+}
+
+pub fn is_token_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> HeaderMap {
+        let mut fields = HeaderMap::new();
+        fields.append("Accept", "text/html");
+        fields.append("Host", "localhost");
+        fields.append("accept", "application/json");
+        fields.append("ACCEPT", "*/*");
+        fields
+    }
+
+    #[test]
+    fn names_match_case_insensitively() {
+        let fields = fields();
+        assert_eq!(fields.get("HOST"), Some("localhost"));
+        assert_eq!(fields.get("aCcEpT"), Some("text/html"));
+        assert!(fields.contains("host"));
+        assert!(!fields.contains("hosts"));
+        assert_eq!(fields.get("missing"), None);
+    }
+
+    #[test]
+    fn keeps_every_value_in_order() {
+        let fields = fields();
+        assert_eq!(
+            fields.get_all("Accept").collect::<Vec<_>>(),
+            ["text/html", "application/json", "*/*"]
+        );
+        assert_eq!(
+            fields.get_joined("accept").as_deref(),
+            Some("text/html, application/json, */*")
+        );
+        assert_eq!(fields.get_joined("missing"), None);
+        assert_eq!(fields.len(), 4);
+    }
+
+    #[test]
+    fn insert_and_remove_cover_every_spelling() {
+        let mut fields = fields();
+        fields.insert("accept", "text/plain");
+        assert_eq!(fields.get_all("Accept").collect::<Vec<_>>(), ["text/plain"]);
+        assert_eq!(fields.len(), 2);
+
+        fields.remove("ACCEPT");
+        assert!(!fields.contains("accept"));
+        assert_eq!(
+            fields.iter().collect::<Vec<_>>(),
+            [("Host", "localhost")]
+        );
+    }
+
+    #[test]
+    fn validate_accepts_writable_fields() {
+        assert!(validate("X-Request-Id", "abc 123").is_ok());
+        assert!(validate("x!#$%&'*+-.^_`|~", "").is_ok());
+        assert!(validate("X-Tab", "a\tb").is_ok());
+    }
+
+    #[test]
+    fn validate_refuses_fields_that_could_start_a_line() {
+        for (name, value) in [
+            ("X-Injected", "a\r\nSet-Cookie: session=stolen"),
+            ("X-Injected", "a\nb"),
+            ("X-Injected", "a\rb"),
+            ("X-Injected", "a\0b"),
+            ("", "value"),
+            ("X Space", "value"),
+            ("X-Colon:", "value"),
+            ("X-Injected\r\nSet-Cookie", "value"),
+            ("X-Héader", "value"),
+        ] {
+            let error = validate(name, value).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput, "{:?}: {:?}", name, value);
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! HTTP/1.1 server based on the codecrafters.io project, usable as a
+//! library: route requests with a [`Router`], wrap it in middlewares with a
+//! [`routing::Stack`] and hand the result to [`Server::serve`].
+
+mod connection;
+pub mod encoding;
+pub mod header;
+pub mod request;
+pub mod response;
+pub mod routing;
+pub mod server;
+
+pub use request::HttpRequest;
+pub use response::builder::HttpResponseBuilder;
+pub use response::HttpResponse;
+pub use routing::{Handler, Middleware, Router};
+pub use server::{RequestLimits, Server, ServerContext};
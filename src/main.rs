@@ -1,19 +1,14 @@
-mod request;
-mod response;
-mod routing;
-mod server;
-mod encoding;
-
 use std::io::Result;
-use std::net::TcpListener;
 use std::path::PathBuf;
-use std::sync::Arc;
-use std::thread;
+use std::process::ExitCode;
+use std::time::Duration;
 
 use clap::Parser;
 
-use routing::handle_connection;
-use server::ServerContext;
+use http_server_starter_rust::{routing, RequestLimits, Server};
+
+/// Where to listen when no address is given.
+const DEFAULT_LISTEN: &str = "127.0.0.1:4221";
 
 /// Simple HTTP server based on codecrafters.io project.
 #[derive(Parser, Debug)]
@@ -22,37 +17,123 @@ struct Args {
     /// Directory to host files.
     #[arg(short, long)]
     directory: Option<String>,
+
+    /// Address to listen on as `host:port`, e.g. `0.0.0.0:80` or `[::1]:4221`.
+    /// Can be given multiple times. Defaults to 127.0.0.1:4221 unless a Unix
+    /// socket is used.
+    #[arg(short, long)]
+    listen: Vec<String>,
+
+    /// Path of a Unix domain socket to listen on.
+    #[arg(long)]
+    unix_socket: Option<PathBuf>,
+
+    /// Permissions of the Unix socket file, in octal.
+    #[arg(long, default_value = "660", value_parser = parse_mode)]
+    unix_socket_mode: u32,
+
+    /// Seconds to keep an idle persistent connection open.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    keep_alive_timeout: u64,
+
+    /// Seconds a client gets to send the request line and headers.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    header_timeout: u64,
+
+    /// Seconds sending the request body may stall.
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    body_timeout: u64,
+
+    /// Seconds writing a response may stall on a client that reads nothing.
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    write_timeout: u64,
+
+    /// Number of runtime threads serving connections.
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u64).range(1..))]
+    workers: u64,
+
+    /// Maximum number of connections served at once.
+    /// Connections beyond that are answered with 503 Service Unavailable.
+    #[arg(long, default_value_t = 16384)]
+    max_connections: u32,
+
+    /// Longest request line accepted, in bytes.
+    #[arg(long, default_value_t = 8192)]
+    max_request_line: usize,
+
+    /// Longest single header line accepted, in bytes.
+    #[arg(long, default_value_t = 8192)]
+    max_header_size: usize,
+
+    /// Most headers accepted in one request.
+    #[arg(long, default_value_t = 100)]
+    max_headers: usize,
+
+    /// Largest request body accepted, in bytes.
+    #[arg(long, default_value_t = 64 * 1024 * 1024)]
+    max_body_size: usize,
+
+    /// Seconds to let active connections finish after SIGTERM or SIGINT.
+    #[arg(long, default_value_t = 30)]
+    drain_timeout: u64,
 }
 
-fn make_server_context() -> ServerContext {
-    let args = Args::parse();
+fn parse_mode(mode: &str) -> std::result::Result<u32, String> {
+    u32::from_str_radix(mode, 8).map_err(|e| format!("invalid octal mode {}: {}", mode, e))
+}
+
+/// Binds every socket `args` asks for and applies its settings.
+fn make_server(args: &Args) -> Result<Server> {
     println!("directory: {:?}", args.directory);
-    ServerContext {
-        host_files_path: args.directory.and_then(|s| Some(PathBuf::from(s))),
+    let mut server = Server::new();
+    if args.listen.is_empty() && args.unix_socket.is_none() {
+        server = server.listen(DEFAULT_LISTEN)?;
+    }
+    for address in &args.listen {
+        server = server.listen(address)?;
+    }
+    if let Some(path) = &args.unix_socket {
+        server = server.listen_unix(path, args.unix_socket_mode)?;
     }
+    if let Some(directory) = &args.directory {
+        server = server.files(directory);
+    }
+    Ok(server
+        .keep_alive_timeout(Duration::from_secs(args.keep_alive_timeout))
+        .header_timeout(Duration::from_secs(args.header_timeout))
+        .body_timeout(Duration::from_secs(args.body_timeout))
+        .write_timeout(Duration::from_secs(args.write_timeout))
+        .limits(RequestLimits {
+            max_request_line: args.max_request_line,
+            max_header_size: args.max_header_size,
+            max_headers: args.max_headers,
+            max_body_size: args.max_body_size,
+        })
+        .max_connections(args.max_connections)
+        .drain_timeout(Duration::from_secs(args.drain_timeout)))
 }
 
-fn main() -> Result<()> {
-    let server_context = Arc::new(make_server_context());
-    let listener: TcpListener = TcpListener::bind("127.0.0.1:4221").unwrap();
-
-    println!("Logs from your program will appear here!");
-    for stream in listener.incoming() {
-        match stream {
-            Ok(_stream) => {
-                println!("Accepted new connection");
-                let context = Arc::clone(&server_context);
-                thread::spawn(move || match handle_connection(_stream, context) {
-                    Err(e) => {
-                        println!("Error in connection: {}", e);
-                    }
-                    Ok(()) => {}
-                });
-            }
-            Err(e) => {
-                println!("Error: {}", e);
-            }
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(args.workers as usize)
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start runtime: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let served = make_server(&args).and_then(|server| {
+        runtime.block_on(server.serve(routing::service()))
+    });
+    match served {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
         }
     }
-    Ok(())
 }
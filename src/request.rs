@@ -1,29 +1,47 @@
 mod builder;
+mod chunked;
+mod parser;
+pub mod target;
 
+use crate::connection::ReadTimeout;
 use crate::encoding::types::ContentEncoding;
+use crate::header::{self, HeaderMap};
 use crate::server;
 
 use std::collections::HashSet;
 use std::fmt;
 use std::io::Error;
 use std::io::ErrorKind;
-use std::io::Read;
 use std::io::Result;
-use std::net::TcpStream;
 use std::sync::Arc;
 
+use itertools::Itertools;
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+use tokio::time::timeout;
+
 use builder::HttpRequestBuilder;
 use builder::HttpRequestHeaderBuilder;
+use parser::Status;
+use target::{Params, Query};
+
+use server::{RequestLimits, ServerContext};
 
-use server::ServerContext;
+const LINE_DELIMITER: &[u8] = b"\r\n";
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum HttpRequestType {
     Get,
+    Head,
     Post,
     Put,
     Delete,
-    // may add more here...
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// Any other method, kept as sent.
+    Extension(String),
 }
 
 impl fmt::Display for HttpRequestType {
@@ -36,44 +54,230 @@ impl HttpRequestType {
     fn from_str(type_str: &str) -> Result<HttpRequestType> {
         match type_str {
             "GET" => Ok(HttpRequestType::Get),
+            "HEAD" => Ok(HttpRequestType::Head),
             "POST" => Ok(HttpRequestType::Post),
             "PUT" => Ok(HttpRequestType::Put),
             "DELETE" => Ok(HttpRequestType::Delete),
+            "CONNECT" => Ok(HttpRequestType::Connect),
+            "OPTIONS" => Ok(HttpRequestType::Options),
+            "TRACE" => Ok(HttpRequestType::Trace),
+            "PATCH" => Ok(HttpRequestType::Patch),
+            // Methods are case-sensitive, so "get" is an extension too.
+            _ if header::is_token(type_str) => {
+                Ok(HttpRequestType::Extension(String::from(type_str)))
+            }
             _ => Err(Error::new(
                 ErrorKind::InvalidData,
-                format!("Request type is invalid: {}", type_str),
+                RequestError::MalformedRequestLine(format!("invalid method {:?}", type_str)),
             )),
         }
     }
+
+    /// The method as it appears on the request line.
+    pub fn as_str(&self) -> &str {
+        match self {
+            HttpRequestType::Get => "GET",
+            HttpRequestType::Head => "HEAD",
+            HttpRequestType::Post => "POST",
+            HttpRequestType::Put => "PUT",
+            HttpRequestType::Delete => "DELETE",
+            HttpRequestType::Connect => "CONNECT",
+            HttpRequestType::Options => "OPTIONS",
+            HttpRequestType::Trace => "TRACE",
+            HttpRequestType::Patch => "PATCH",
+            HttpRequestType::Extension(method) => method,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HttpVersion {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+impl HttpVersion {
+    fn from_str(version_str: &str) -> Result<HttpVersion> {
+        match version_str {
+            "HTTP/1.0" => Ok(HttpVersion::Http10),
+            "HTTP/1.1" => Ok(HttpVersion::Http11),
+            // The parser has checked the syntax, this is just a version we
+            // don't speak.
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                RequestError::UnsupportedVersion(String::from(version_str)),
+            )),
+        }
+    }
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+        }
+    }
+}
+
+/// Why a request could not be read. Carried inside the `io::Error` returned
+/// by [`HttpRequest::read_from_stream`] so the connection can answer with a
+/// matching status.
+#[derive(Debug, Error)]
+pub enum RequestError {
+    #[error("timed out reading request headers")]
+    HeaderTimeout,
+    #[error("timed out reading request body")]
+    BodyTimeout,
+    #[error("request line is longer than {0} bytes")]
+    RequestLineTooLong(usize),
+    #[error("header line is longer than {0} bytes")]
+    HeaderTooLarge(usize),
+    #[error("more than {0} headers")]
+    TooManyHeaders(usize),
+    #[error("body of {0} bytes is larger than {1} bytes")]
+    BodyTooLarge(usize, usize),
+    #[error("both Content-Length and Transfer-Encoding are set")]
+    ConflictingFraming,
+    #[error("unsupported transfer encoding: {0}")]
+    UnsupportedTransferEncoding(String),
+    #[error("chunked is applied more than once")]
+    RepeatedChunked,
+    #[error("malformed chunked body: {0}")]
+    MalformedChunk(String),
+    #[error("malformed request line: {0}")]
+    MalformedRequestLine(String),
+    #[error("request target is not valid UTF-8")]
+    NonUtf8Target,
+    #[error("malformed header: {0}")]
+    MalformedHeader(String),
+    #[error("invalid Content-Length: {0:?}")]
+    InvalidContentLength(String),
+    #[error("invalid percent-encoding in request target")]
+    InvalidPercentEncoding,
+    #[error("unsupported HTTP version: {0}")]
+    UnsupportedVersion(String),
+    #[error("HTTP/1.1 requests need exactly one Host header")]
+    MissingHost,
+}
+
+impl RequestError {
+    /// Extracts the request error wrapped in `error`, if any.
+    pub fn from_io(error: &Error) -> Option<&RequestError> {
+        error.get_ref().and_then(|inner| inner.downcast_ref())
+    }
 }
 
 pub struct HttpRequestHeader {
-    pub host: String,
-    pub user_agent: String,
-    pub accept: String,
-    pub content_type: String,
-    pub content_length: usize,
-    pub accept_encoding: HashSet<ContentEncoding>,
+    /// Every header field the client sent.
+    pub fields: HeaderMap,
+    content_length: usize,
+    accept_encoding: HashSet<ContentEncoding>,
+}
+
+impl HttpRequestHeader {
+    /// First value of the header `name`, matched case-insensitively.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.get(name)
+    }
+
+    pub fn host(&self) -> &str {
+        self.get("host").unwrap_or("unknown")
+    }
+
+    pub fn user_agent(&self) -> &str {
+        self.get("user-agent").unwrap_or("unknown")
+    }
+
+    pub fn accept(&self) -> &str {
+        self.get("accept").unwrap_or("*/*")
+    }
+
+    pub fn content_type(&self) -> &str {
+        self.get("content-type").unwrap_or("")
+    }
+
+    pub fn content_length(&self) -> usize {
+        self.content_length
+    }
+
+    /// Encodings the client accepts, never empty.
+    pub fn accept_encoding(&self) -> &HashSet<ContentEncoding> {
+        &self.accept_encoding
+    }
+
+    pub fn connection(&self) -> String {
+        self.fields.get_joined("connection").unwrap_or_default()
+    }
+
+    pub fn transfer_encoding(&self) -> String {
+        self.fields
+            .get_joined("transfer-encoding")
+            .unwrap_or_default()
+    }
+
+    /// Whether the body is sent with `Transfer-Encoding: chunked`. Chunked has
+    /// to be the one and only coding: we can't undo any other, and a proxy in
+    /// front of us may frame a body chunked twice differently than we do.
+    pub fn is_chunked(&self) -> std::result::Result<bool, RequestError> {
+        let Some(value) = self.fields.get_joined("transfer-encoding") else {
+            return Ok(false);
+        };
+        let codings = value.split(',').map(str::trim).collect_vec();
+        if !codings.iter().all(|coding| header::is_token(coding)) {
+            return Err(RequestError::MalformedHeader(format!(
+                "invalid Transfer-Encoding: {:?}",
+                value
+            )));
+        }
+        if !codings
+            .iter()
+            .all(|coding| coding.eq_ignore_ascii_case("chunked"))
+        {
+            return Err(RequestError::UnsupportedTransferEncoding(value));
+        }
+        if codings.len() > 1 {
+            return Err(RequestError::RepeatedChunked);
+        }
+        Ok(true)
+    }
 }
 
 impl fmt::Display for HttpRequestHeader {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "HttpRequestHeader{{")?;
-        write!(f, "Host: {:?}, ", self.host)?;
-        write!(f, "User-Agent: {:?}, ", self.user_agent)?;
-        write!(f, "Accept: {:?}, ", self.accept)?;
-        write!(f, "Content-Type: {:?}, ", self.content_type)?;
-        write!(f, "Content-Length: {:?}, ", self.content_length)?;
-        write!(f, "Accept-Encoding: {:?}, ", self.accept_encoding)?;
+        write!(f, "Host: {:?}, ", self.host())?;
+        write!(f, "User-Agent: {:?}, ", self.user_agent())?;
+        write!(f, "Accept: {:?}, ", self.accept())?;
+        write!(f, "Content-Type: {:?}, ", self.content_type())?;
+        write!(f, "Content-Length: {:?}, ", self.content_length())?;
+        write!(f, "Accept-Encoding: {:?}, ", self.accept_encoding())?;
+        write!(f, "Connection: {:?}, ", self.connection())?;
+        write!(f, "Transfer-Encoding: {:?}, ", self.transfer_encoding())?;
+        write!(f, "Fields (len): {}, ", self.fields.len())?;
         write!(f, "}}")
     }
 }
 
 pub struct HttpRequest {
     pub request_type: HttpRequestType,
+    /// Path of the target, percent-decoded.
     pub path: String,
+    /// Parameters after the '?' of the target.
+    pub query: Query,
+    /// The target exactly as it was sent.
+    pub target: String,
+    /// What the matched route's pattern took from the path.
+    pub params: Params,
+    pub version: HttpVersion,
     pub header: HttpRequestHeader,
     pub body: Vec<u8>,
+    /// Trailer fields sent after a chunked body.
+    pub trailers: HeaderMap,
     pub context: Arc<ServerContext>,
 }
 
@@ -81,67 +285,240 @@ impl fmt::Display for HttpRequest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "HttpRequest{{")?;
         write!(f, "Type: {}, ", self.request_type)?;
+        write!(f, "Target: {}, ", self.target)?;
         write!(f, "Path: {}, ", self.path)?;
+        if !self.query.is_empty() {
+            write!(f, "Query: {}, ", self.query)?;
+        }
+        if !self.params.is_empty() {
+            write!(f, "Params: {}, ", self.params)?;
+        }
+        write!(f, "Version: {}, ", self.version)?;
         write!(f, "Header: {}, ", self.header)?;
         write!(f, "Body (len): {}, ", self.body.len())?;
+        if !self.trailers.is_empty() {
+            write!(f, "Trailers: {}, ", self.trailers)?;
+        }
         write!(f, "}}")
     }
 }
 
 impl HttpRequest {
-    pub fn read_from_stream(
-        mut stream: &TcpStream,
+    /// Reads one request from `stream`. The reader is expected to live as long
+    /// as the connection, so bytes of pipelined requests that were read along
+    /// with this one stay buffered for the next call.
+    pub async fn read_from_stream(
+        stream: &mut (impl AsyncBufRead + Unpin),
         server_context: Arc<ServerContext>,
     ) -> Result<HttpRequest> {
-        let mut read_buffer: Vec<u8> = vec![];
-        read_buffer.reserve(128);
-        read_line(&mut stream, &mut read_buffer)?;
-        let mut request_builder = HttpRequestBuilder::from_request_line(
-            &String::from_utf8_lossy(&read_buffer),
-            server_context,
-        )?;
-
-        // Read for request header lines
-        let mut request_header_builder = HttpRequestHeaderBuilder::new();
-        loop {
-            // Clear read buffer, Read for one line
-            read_buffer.clear();
-            read_line(&mut stream, &mut read_buffer)?;
-            if read_buffer.is_empty() {
-                // end streaming
-                break;
-            }
-            request_header_builder =
-                request_header_builder.apply_from_line(&String::from_utf8_lossy(&read_buffer));
-        }
-        let request_header = request_header_builder.build();
-        if request_header.content_length > 0 {
-            let mut body: Vec<u8> = vec![];
-            body.resize(request_header.content_length, 0);
-            stream.read_exact(body.as_mut_slice())?;
+        let header_timeout = server_context.header_read_timeout;
+        let body_timeout = server_context.body_read_timeout;
+        let limits = server_context.limits;
+        let max_body_size = limits.max_body_size;
+        let (mut request_builder, request_header) =
+            timeout(header_timeout, read_head(stream, server_context))
+                .await
+                .map_err(|_| Error::new(ErrorKind::TimedOut, RequestError::HeaderTimeout))??;
+
+        let chunked = request_header
+            .is_chunked()
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if chunked {
+            let mut stream = ReadTimeout::new(stream, body_timeout);
+            let (body, trailers) = chunked::read_body(&mut stream, &limits)
+                .await
+                .map_err(body_timed_out)?;
+            return Ok(request_builder
+                .body(body)
+                .trailers(trailers)
+                .header(request_header)
+                .build());
+        }
+
+        if request_header.content_length() > max_body_size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                RequestError::BodyTooLarge(request_header.content_length(), max_body_size),
+            ));
+        }
+        if request_header.content_length() > 0 {
+            let mut body: Vec<u8> = vec![0; request_header.content_length()];
+            ReadTimeout::new(stream, body_timeout)
+                .read_exact(body.as_mut_slice())
+                .await
+                .map_err(body_timed_out)?;
             request_builder = request_builder.body(body);
         }
 
         Ok(request_builder.header(request_header).build())
     }
+
+    /// Whether the client wants the connection kept open after this request.
+    /// HTTP/1.1 defaults to persistent, HTTP/1.0 has to opt in.
+    pub fn keep_alive(&self) -> bool {
+        let tokens = self
+            .header
+            .connection()
+            .split(',')
+            .map(|token| token.trim().to_lowercase())
+            .collect_vec();
+        if tokens.iter().any(|token| token == "close") {
+            return false;
+        }
+        match self.version {
+            HttpVersion::Http10 => tokens.iter().any(|token| token == "keep-alive"),
+            HttpVersion::Http11 => true,
+        }
+    }
 }
 
-fn read_line(mut stream: &TcpStream, read_buffer: &mut Vec<u8>) -> Result<()> {
-    let delimiter: &[u8] = b"\r\n";
-    // Read for first line
+/// Tells a body that stopped arriving apart from other errors reading it.
+fn body_timed_out(e: Error) -> Error {
+    if e.kind() == ErrorKind::TimedOut {
+        return Error::new(ErrorKind::TimedOut, RequestError::BodyTimeout);
+    }
+    e
+}
+
+/// Reads the request line and headers.
+async fn read_head(
+    stream: &mut (impl AsyncBufRead + Unpin),
+    server_context: Arc<ServerContext>,
+) -> Result<(HttpRequestBuilder, HttpRequestHeader)> {
+    let limits = server_context.limits;
+    let mut read_buffer: Vec<u8> = Vec::with_capacity(128);
+    let request_builder = loop {
+        read_raw_line(
+            stream,
+            &mut read_buffer,
+            limits.max_request_line,
+            RequestError::RequestLineTooLong(limits.max_request_line),
+        )
+        .await?;
+        match parser::request_line(&read_buffer) {
+            Ok(Status::Complete(line, _)) => {
+                break HttpRequestBuilder::from_request_line(line, server_context)?
+            }
+            // Only the empty line that may come first, read on.
+            Ok(Status::Partial) => {}
+            Err(e) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    RequestError::MalformedRequestLine(e.to_string()),
+                ))
+            }
+        }
+    };
+
+    let mut request_header_builder = HttpRequestHeaderBuilder::new();
+    for (name, value) in read_fields(stream, &limits).await?.iter() {
+        request_header_builder = request_header_builder.apply_field(name, value)?;
+    }
+    if request_header_builder.has_conflicting_framing() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            RequestError::ConflictingFraming,
+        ));
+    }
+    let request_header = request_header_builder.build();
+    if request_builder.requires_host() && request_header.fields.get_all("host").count() != 1 {
+        return Err(Error::new(ErrorKind::InvalidData, RequestError::MissingHost));
+    }
+    Ok((request_builder, request_header))
+}
+
+/// Reads header or trailer fields up to the empty line that ends them.
+async fn read_fields(
+    stream: &mut (impl AsyncBufRead + Unpin),
+    limits: &RequestLimits,
+) -> Result<HeaderMap> {
+    let mut fields = HeaderMap::new();
+    let mut read_buffer: Vec<u8> = Vec::with_capacity(128);
+    let mut line_count = 0;
     loop {
-        // Can probably be optimized
-        let mut single_byte: [u8; 1] = [0];
-        stream.read_exact(&mut single_byte)?;
-        read_buffer.push(single_byte[0]);
-        if single_byte[0] == delimiter[delimiter.len() - 1]
-            && read_buffer.len() >= delimiter.len()
-            && &read_buffer[read_buffer.len() - delimiter.len()..] == delimiter
-        {
-            // Remove delimiter from buffer
-            read_buffer.truncate(read_buffer.len() - delimiter.len());
-            break;
+        match parser::header_field(&read_buffer) {
+            Ok(Status::Complete(Some(field), length)) => {
+                fields.append(
+                    &String::from_utf8_lossy(field.name),
+                    &String::from_utf8_lossy(&field.value),
+                );
+                read_buffer.drain(..length);
+            }
+            Ok(Status::Complete(None, _)) => return Ok(fields),
+            Ok(Status::Partial) => {
+                let start = read_buffer.len();
+                read_raw_line(
+                    stream,
+                    &mut read_buffer,
+                    limits.max_header_size,
+                    RequestError::HeaderTooLarge(limits.max_header_size),
+                )
+                .await?;
+                // Folded lines count as well, so one field can't grow forever.
+                if &read_buffer[start..] != b"\r\n" {
+                    line_count += 1;
+                    if line_count > limits.max_headers {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            RequestError::TooManyHeaders(limits.max_headers),
+                        ));
+                    }
+                }
+            }
+            Err(e) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    RequestError::MalformedHeader(format!(
+                        "{} in {:?}",
+                        e,
+                        String::from_utf8_lossy(&read_buffer).trim_end()
+                    )),
+                ))
+            }
         }
     }
+}
+
+/// Reads one line of at most `max_length` bytes, excluding the delimiter,
+/// failing with `too_long` once the line grows past that.
+async fn read_line(
+    stream: &mut (impl AsyncBufRead + Unpin),
+    read_buffer: &mut Vec<u8>,
+    max_length: usize,
+    too_long: RequestError,
+) -> Result<()> {
+    read_raw_line(stream, read_buffer, max_length, too_long).await?;
+    // Remove delimiter from buffer
+    read_buffer.truncate(read_buffer.len() - LINE_DELIMITER.len());
     Ok(())
 }
+
+/// Appends one line to `read_buffer`, delimiter included, like [`read_line`].
+async fn read_raw_line(
+    stream: &mut (impl AsyncBufRead + Unpin),
+    read_buffer: &mut Vec<u8>,
+    max_length: usize,
+    too_long: RequestError,
+) -> Result<()> {
+    let start = read_buffer.len();
+    let mut limited = stream.take((max_length + LINE_DELIMITER.len()) as u64);
+    // A lone '\n' may show up inside a line, keep reading until we see "\r\n".
+    loop {
+        if limited
+            .read_until(LINE_DELIMITER[LINE_DELIMITER.len() - 1], read_buffer)
+            .await?
+            == 0
+        {
+            if limited.limit() == 0 {
+                return Err(Error::new(ErrorKind::InvalidData, too_long));
+            }
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Connection closed while reading a line",
+            ));
+        }
+        if read_buffer[start..].ends_with(LINE_DELIMITER) {
+            return Ok(());
+        }
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::needless_arbitrary_self_type)]
+
 use std::{
     collections::HashSet,
     io::{Error, ErrorKind, Result},
@@ -6,16 +8,19 @@ use std::{
 
 use itertools::Itertools;
 
-use crate::{encoding::types::ContentEncoding, server::ServerContext};
+use crate::{
+    encoding::types::ContentEncoding,
+    header::HeaderMap,
+    server::ServerContext,
+};
 
-use super::{HttpRequest, HttpRequestHeader, HttpRequestType};
+use super::parser::RequestLine;
+use super::target::{self, Params, Query};
+use super::{HttpRequest, HttpRequestHeader, HttpRequestType, HttpVersion, RequestError};
 
 #[derive(Default)]
 pub struct HttpRequestHeaderBuilder {
-    host: Option<String>,
-    user_agent: Option<String>,
-    accept: Option<String>,
-    content_type: Option<String>,
+    fields: HeaderMap,
     content_length: Option<usize>,
     accept_encoding: HashSet<ContentEncoding>,
 }
@@ -25,44 +30,27 @@ impl HttpRequestHeaderBuilder {
         HttpRequestHeaderBuilder::default()
     }
 
-    pub fn apply_from_line(self: Self, line: &str) -> Self {
-        let key_value = line.splitn(2, ": ").collect_vec();
-        if key_value.len() < 2 {
-            println!("WARNING: ignoring header {}", line);
-            return self;
-        }
-        match key_value[0].to_lowercase().as_str() {
-            "host" => self.host(String::from(key_value[1])),
-            "user-agent" => self.user_agent(String::from(key_value[1])),
-            "accept" => self.accept(String::from(key_value[1])),
-            "content-type" => self.content_type(String::from(key_value[1])),
-            "content-length" => self.content_length(key_value[1].parse::<usize>().unwrap_or(0)),
-            // encoding can be multiple schemas separated by a comma
-            "accept-encoding" => self.accept_encodings_from_line(key_value[1]),
-            _ => {
-                println!("WARNING: unknown header key: {}", key_value[0]);
-                self
+    /// Takes in a field as the parser produced it.
+    pub fn apply_field(self, name: &str, value: &str) -> Result<Self> {
+        let builder = match name.to_lowercase().as_str() {
+            "content-length" => {
+                let content_length = parse_content_length(value)?;
+                // Repeating the same length is allowed, disagreeing is not.
+                if self.content_length.is_some_and(|length| length != content_length) {
+                    return Err(invalid_content_length(value));
+                }
+                self.content_length(content_length)
             }
-        }
-    }
-
-    pub fn host(mut self: Self, host: String) -> Self {
-        self.host = Some(host);
-        self
-    }
-
-    pub fn user_agent(mut self: Self, user_agent: String) -> Self {
-        self.user_agent = Some(user_agent);
-        self
-    }
-
-    pub fn accept(mut self: Self, accept: String) -> Self {
-        self.accept = Some(accept);
-        self
+            // encoding can be multiple schemas separated by a comma
+            "accept-encoding" => self.accept_encodings_from_line(value),
+            _ => self,
+        };
+        Ok(builder.field(name, value))
     }
 
-    pub fn content_type(mut self: Self, content_type: String) -> Self {
-        self.content_type = Some(content_type);
+    /// Adds a raw header field.
+    pub fn field(mut self, name: &str, value: &str) -> Self {
+        self.fields.append(name, value);
         self
     }
 
@@ -76,16 +64,20 @@ impl HttpRequestHeaderBuilder {
         self
     }
 
+    /// A request framed by both Content-Length and Transfer-Encoding can be
+    /// read differently by us and by a proxy in front of us, so it must be
+    /// rejected.
+    pub fn has_conflicting_framing(&self) -> bool {
+        self.content_length.is_some() && self.fields.contains("transfer-encoding")
+    }
+
     fn accept_encodings_from_line(self: Self, line: &str) -> Self {
-        let delimiter = ", ";
+        let delimiter = ',';
         let encodings = line.split(delimiter).collect_vec();
         let mut builder = self;
         for encoding in encodings {
-            match ContentEncoding::from(encoding) {
-                Some(encoding) => {
-                    builder = builder.accept_encoding(encoding);
-                }
-                _ => {}
+            if let Some(encoding) = ContentEncoding::from(encoding.trim()) {
+                builder = builder.accept_encoding(encoding);
             }
         }
         builder
@@ -93,10 +85,7 @@ impl HttpRequestHeaderBuilder {
 
     pub fn build(self: Self) -> HttpRequestHeader {
         let mut header = HttpRequestHeader {
-            host: self.host.unwrap_or(String::from("unknown")),
-            user_agent: self.user_agent.unwrap_or(String::from("unknown")),
-            accept: self.accept.unwrap_or(String::from("*/*")),
-            content_type: self.content_type.unwrap_or(String::from("")),
+            fields: self.fields,
             content_length: self.content_length.unwrap_or(0),
             accept_encoding: self.accept_encoding,
         };
@@ -113,31 +102,30 @@ pub struct HttpRequestBuilder {
     request_type: HttpRequestType,
     context: Arc<ServerContext>,
     path: String,
+    query: Query,
+    target: String,
+    version: HttpVersion,
     header: Option<HttpRequestHeader>,
     body: Option<Vec<u8>>,
+    trailers: HeaderMap,
 }
 
 impl HttpRequestBuilder {
     pub fn from_request_line(
-        line: &str,
+        line: RequestLine,
         context: Arc<ServerContext>,
     ) -> Result<HttpRequestBuilder> {
-        let components = line.split(" ").collect_vec();
-        if components.len() != 3 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("Invalid request line: {}", line),
-            ));
-        }
-        let request_type = HttpRequestType::from_str(components[0])?;
-        let path = if components[1].is_empty() {
-            String::from("/")
-        } else {
-            components[1].to_string()
-        };
-        // Ignore http version for now.
-
-        Ok(HttpRequestBuilder::new(request_type, path, context))
+        let request_type = HttpRequestType::from_str(&String::from_utf8_lossy(line.method))?;
+        let target = std::str::from_utf8(line.target)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, RequestError::NonUtf8Target))?;
+        let (path, query) =
+            target::split(target).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let version = HttpVersion::from_str(&String::from_utf8_lossy(line.version))?;
+
+        Ok(HttpRequestBuilder::new(request_type, path, context)
+            .query(query)
+            .target(String::from(target))
+            .version(version))
     }
 
     pub fn new(
@@ -148,12 +136,37 @@ impl HttpRequestBuilder {
         HttpRequestBuilder {
             request_type,
             context,
+            target: path.clone(),
             path,
+            query: Query::default(),
+            version: HttpVersion::Http11,
             header: None,
             body: None,
+            trailers: HeaderMap::new(),
         }
     }
 
+    pub fn query(mut self, query: Query) -> Self {
+        self.query = query;
+        self
+    }
+
+    /// The target as sent, `path` by default.
+    pub fn target(mut self, target: String) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn version(mut self, version: HttpVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// HTTP/1.1 has to say which host it is for, HTTP/1.0 predates that.
+    pub fn requires_host(&self) -> bool {
+        self.version == HttpVersion::Http11
+    }
+
     pub fn header(mut self: Self, header: HttpRequestHeader) -> Self {
         self.header = Some(header);
         self
@@ -164,15 +177,41 @@ impl HttpRequestBuilder {
         self
     }
 
+    pub fn trailers(mut self, trailers: HeaderMap) -> Self {
+        self.trailers = trailers;
+        self
+    }
+
     pub fn build(self: Self) -> HttpRequest {
         HttpRequest {
             request_type: self.request_type,
             path: self.path,
+            query: self.query,
+            target: self.target,
+            params: Params::default(),
+            version: self.version,
             header: self
                 .header
                 .unwrap_or(HttpRequestHeaderBuilder::new().build()),
             body: self.body.unwrap_or(vec![]),
+            trailers: self.trailers,
             context: self.context,
         }
     }
 }
+
+/// Content-Length is a plain decimal number, without the sign `parse` takes.
+fn parse_content_length(value: &str) -> Result<usize> {
+    if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid_content_length(value));
+    }
+    value.parse().map_err(|_| invalid_content_length(value))
+}
+
+fn invalid_content_length(value: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        RequestError::InvalidContentLength(String::from(value)),
+    )
+}
+
@@ -0,0 +1,129 @@
+use std::io::{Error, ErrorKind, Result};
+
+use tokio::io::{AsyncBufRead, AsyncReadExt};
+
+use crate::header::HeaderMap;
+use crate::server::RequestLimits;
+
+use super::{read_fields, read_line, RequestError};
+
+/// Decodes a `Transfer-Encoding: chunked` body, returning the body and any
+/// trailer fields sent after it. Chunk extensions are read and ignored.
+pub async fn read_body(
+    stream: &mut (impl AsyncBufRead + Unpin),
+    limits: &RequestLimits,
+) -> Result<(Vec<u8>, HeaderMap)> {
+    let mut body: Vec<u8> = vec![];
+    let mut line: Vec<u8> = Vec::with_capacity(32);
+    loop {
+        line.clear();
+        read_line(
+            stream,
+            &mut line,
+            limits.max_header_size,
+            malformed("chunk size line too long"),
+        )
+        .await?;
+        let size = parse_chunk_size(&line)?;
+        if size == 0 {
+            break;
+        }
+
+        let new_length = body.len().saturating_add(size);
+        if new_length > limits.max_body_size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                RequestError::BodyTooLarge(new_length, limits.max_body_size),
+            ));
+        }
+        let start = body.len();
+        body.resize(new_length, 0);
+        stream.read_exact(&mut body[start..]).await?;
+
+        let mut delimiter = [0u8; 2];
+        stream.read_exact(&mut delimiter).await?;
+        if &delimiter != b"\r\n" {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                malformed("chunk data not followed by CRLF"),
+            ));
+        }
+    }
+
+    Ok((body, read_fields(stream, limits).await?))
+}
+
+/// Reads the `1*HEXDIG` size at the start of a chunk size line. Anything
+/// else a proxy might read differently, like a sign or padding, is refused.
+fn parse_chunk_size(line: &[u8]) -> Result<usize> {
+    // Extensions follow the size after a ';', e.g. "1a;name=value", with
+    // optional whitespace in between.
+    let (size, extensions) = match line.iter().position(|byte| *byte == b';') {
+        Some(end) => (trim_whitespace_end(&line[..end]), &line[end..]),
+        None => (line, &b""[..]),
+    };
+    let invalid = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            malformed(&format!(
+                "invalid chunk size: {:?}",
+                String::from_utf8_lossy(line)
+            )),
+        )
+    };
+    if size.is_empty() || !size.iter().all(u8::is_ascii_hexdigit) {
+        return Err(invalid());
+    }
+    // A proxy ending the line at a lone '\n' would see another chunk.
+    if extensions.iter().any(|byte| *byte == b'\r' || *byte == b'\n') {
+        return Err(invalid());
+    }
+    // Hex digits are ASCII, and what is left to fail is overflow.
+    let size = std::str::from_utf8(size).map_err(|_| invalid())?;
+    usize::from_str_radix(size, 16).map_err(|_| invalid())
+}
+
+fn trim_whitespace_end(bytes: &[u8]) -> &[u8] {
+    let end = bytes
+        .iter()
+        .rposition(|byte| *byte != b' ' && *byte != b'\t')
+        .map_or(0, |last| last + 1);
+    &bytes[..end]
+}
+
+fn malformed(reason: &str) -> RequestError {
+    RequestError::MalformedChunk(String::from(reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_sizes_are_hex_digits_only() {
+        assert_eq!(parse_chunk_size(b"0").unwrap(), 0);
+        assert_eq!(parse_chunk_size(b"1a").unwrap(), 26);
+        assert_eq!(parse_chunk_size(b"00FF").unwrap(), 255);
+        for line in [
+            &b""[..],
+            b"+5",
+            b"-5",
+            b" 5",
+            b"5 ",
+            b"0x5",
+            b"5g",
+            b"\xff",
+            b"10000000000000000",
+        ] {
+            assert!(parse_chunk_size(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn chunk_extensions_are_skipped() {
+        assert_eq!(parse_chunk_size(b"5;name=value").unwrap(), 5);
+        assert_eq!(parse_chunk_size(b"5 \t; name").unwrap(), 5);
+        assert!(parse_chunk_size(b"5;name\nvalue").is_err());
+        assert!(parse_chunk_size(b";name").is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! Request line and header field grammar from RFC 9112, parsed from raw bytes.
+//! Input may stop anywhere, in which case the parsers ask for more instead of
+//! failing, so they can be fed a buffer as it fills.
+
+use std::borrow::Cow;
+
+use nom::bytes::streaming::{tag, take_while, take_while1};
+use nom::character::streaming::{char, crlf, satisfy};
+use nom::combinator::{opt, recognize};
+use nom::error::{context, ContextError, ErrorKind};
+use nom::sequence::tuple;
+use nom::IResult;
+use thiserror::Error;
+
+use crate::header;
+
+/// `method SP request-target SP HTTP-version`, as sent.
+pub struct RequestLine<'a> {
+    pub method: &'a [u8],
+    pub target: &'a [u8],
+    pub version: &'a [u8],
+}
+
+/// A header or trailer field, with the surrounding whitespace trimmed and
+/// folded lines joined.
+pub struct HeaderField<'a> {
+    pub name: &'a [u8],
+    pub value: Cow<'a, [u8]>,
+}
+
+pub enum Status<T> {
+    /// Parsed from the given number of bytes at the start of the input.
+    Complete(T, usize),
+    /// The input ends before anything could be decided.
+    Partial,
+}
+
+/// What the parser was looking for where the input went wrong.
+#[derive(Debug, Error, PartialEq)]
+#[error("expected {expected} at byte {offset}")]
+pub struct ParseError {
+    pub offset: usize,
+    pub expected: &'static str,
+}
+
+/// Parses the request line. A single empty line before it is skipped, as
+/// some clients send one after a request body.
+pub fn request_line(input: &[u8]) -> Result<Status<RequestLine<'_>>, ParseError> {
+    run(input, parse_request_line)
+}
+
+/// Parses one header field, or `None` for the empty line ending the fields.
+/// A field is only complete once the next line shows it isn't folded.
+pub fn header_field(input: &[u8]) -> Result<Status<Option<HeaderField<'_>>>, ParseError> {
+    run(input, parse_header_field)
+}
+
+fn run<'a, T>(
+    input: &'a [u8],
+    mut parser: impl FnMut(&'a [u8]) -> Parsed<'a, T>,
+) -> Result<Status<T>, ParseError> {
+    match parser(input) {
+        Ok((rest, parsed)) => Ok(Status::Complete(parsed, input.len() - rest.len())),
+        Err(nom::Err::Incomplete(_)) => Ok(Status::Partial),
+        Err(nom::Err::Error(failure) | nom::Err::Failure(failure)) => Err(ParseError {
+            offset: input.len() - failure.input.len(),
+            expected: failure.expected.unwrap_or("valid syntax"),
+        }),
+    }
+}
+
+type Parsed<'a, T> = IResult<&'a [u8], T, Failure<'a>>;
+
+/// Error carrying the innermost context it failed in.
+struct Failure<'a> {
+    input: &'a [u8],
+    expected: Option<&'static str>,
+}
+
+impl<'a> nom::error::ParseError<&'a [u8]> for Failure<'a> {
+    fn from_error_kind(input: &'a [u8], _kind: ErrorKind) -> Self {
+        Failure {
+            input,
+            expected: None,
+        }
+    }
+
+    fn append(_input: &'a [u8], _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a> ContextError<&'a [u8]> for Failure<'a> {
+    fn add_context(_input: &'a [u8], context: &'static str, other: Self) -> Self {
+        Failure {
+            expected: other.expected.or(Some(context)),
+            ..other
+        }
+    }
+}
+
+fn parse_request_line(input: &[u8]) -> Parsed<'_, RequestLine<'_>> {
+    let (input, _) = opt(crlf)(input)?;
+    let (input, method) = context("a method", take_while1(header::is_token_char))(input)?;
+    let (input, _) = context("a space after the method", char(' '))(input)?;
+    let (input, target) = context("a request target", take_while1(is_target_char))(input)?;
+    let (input, _) = context("a space after the request target", char(' '))(input)?;
+    let (input, version) = context(
+        "an HTTP version",
+        recognize(tuple((tag("HTTP/"), digit, char('.'), digit))),
+    )(input)?;
+    let (input, _) = context("CRLF after the HTTP version", crlf)(input)?;
+    Ok((
+        input,
+        RequestLine {
+            method,
+            target,
+            version,
+        },
+    ))
+}
+
+fn parse_header_field(input: &[u8]) -> Parsed<'_, Option<HeaderField<'_>>> {
+    if let (rest, Some(_)) = opt(crlf)(input)? {
+        return Ok((rest, None));
+    }
+    let (input, name) = context("a field name", take_while1(header::is_token_char))(input)?;
+    let (input, _) = context("':' after the field name", char(':'))(input)?;
+    let (mut input, _) = take_while(is_whitespace)(input)?;
+
+    let mut lines: Vec<&[u8]> = vec![];
+    loop {
+        let (rest, line) = take_while(is_field_char)(input)?;
+        let (rest, _) = context("CRLF after the field value", crlf)(rest)?;
+        let line = trim_end(line);
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        // obs-fold: a line starting with whitespace continues the value.
+        let (rest, fold) = take_while(is_whitespace)(rest)?;
+        input = rest;
+        if fold.is_empty() {
+            break;
+        }
+    }
+
+    let value = match lines.as_slice() {
+        [] => Cow::Borrowed(&b""[..]),
+        [line] => Cow::Borrowed(*line),
+        lines => Cow::Owned(lines.join(&b' ')),
+    };
+    Ok((input, Some(HeaderField { name, value })))
+}
+
+fn digit(input: &[u8]) -> Parsed<'_, char> {
+    satisfy(|c| c.is_ascii_digit())(input)
+}
+
+fn trim_end(line: &[u8]) -> &[u8] {
+    let end = line
+        .iter()
+        .rposition(|byte| !is_whitespace(*byte))
+        .map_or(0, |last| last + 1);
+    &line[..end]
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    byte == b' ' || byte == b'\t'
+}
+
+/// Anything visible. Whether it decodes is up to the caller.
+fn is_target_char(byte: u8) -> bool {
+    byte > b' ' && byte != 0x7f
+}
+
+/// Visible characters and whitespace, including obs-text.
+fn is_field_char(byte: u8) -> bool {
+    byte == b'\t' || (byte >= b' ' && byte != 0x7f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete_line(input: &[u8]) -> (RequestLine<'_>, usize) {
+        match request_line(input) {
+            Ok(Status::Complete(line, consumed)) => (line, consumed),
+            Ok(Status::Partial) => panic!("partial: {:?}", input),
+            Err(e) => panic!("{}: {:?}", e, input),
+        }
+    }
+
+    fn complete_field(input: &[u8]) -> (Option<HeaderField<'_>>, usize) {
+        match header_field(input) {
+            Ok(Status::Complete(field, consumed)) => (field, consumed),
+            Ok(Status::Partial) => panic!("partial: {:?}", input),
+            Err(e) => panic!("{}: {:?}", e, input),
+        }
+    }
+
+    #[test]
+    fn parses_request_lines() {
+        let (line, consumed) = complete_line(b"GET /a?b=c HTTP/1.1\r\nHost: x\r\n");
+        assert_eq!(line.method, b"GET");
+        assert_eq!(line.target, b"/a?b=c");
+        assert_eq!(line.version, b"HTTP/1.1");
+        assert_eq!(consumed, 21);
+
+        // One empty line before the request line is skipped.
+        let (line, consumed) = complete_line(b"\r\nBREW * HTTP/1.0\r\n");
+        assert_eq!(line.method, b"BREW");
+        assert_eq!(line.target, b"*");
+        assert_eq!(consumed, 19);
+    }
+
+    #[test]
+    fn request_line_prefixes_are_partial() {
+        let input = b"GET / HTTP/1.1\r\n";
+        for end in 0..input.len() {
+            assert!(
+                matches!(request_line(&input[..end]), Ok(Status::Partial)),
+                "{:?}",
+                &input[..end]
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_request_lines_say_where() {
+        for (input, offset, expected) in [
+            (&b"GET /\r\n"[..], 5, "a space after the request target"),
+            (b"GET  / HTTP/1.1\r\n", 4, "a request target"),
+            (b"G(T / HTTP/1.1\r\n", 1, "a space after the method"),
+            (b"GET / http/1.1\r\n", 6, "an HTTP version"),
+            (b"GET / HTTP/11\r\n", 12, "an HTTP version"),
+            (b"GET / HTTP/1.1\n", 14, "CRLF after the HTTP version"),
+            (b"GET / HTTP/1.1 \r\n", 14, "CRLF after the HTTP version"),
+            (b"\r\n\r\nGET / HTTP/1.1\r\n", 2, "a method"),
+        ] {
+            let error = request_line(input).err().expect("should fail");
+            assert_eq!(error, ParseError { offset, expected }, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parses_header_fields() {
+        let (field, consumed) = complete_field(b"Host: localhost\r\nAccept: */*\r\n");
+        let field = field.unwrap();
+        assert_eq!(field.name, b"Host");
+        assert_eq!(&*field.value, b"localhost");
+        assert_eq!(consumed, 17);
+
+        let (field, _) = complete_field(b"X-Spaced:\t a  b \t\r\n\r\n");
+        assert_eq!(&*field.unwrap().value, b"a  b");
+
+        let (field, _) = complete_field(b"X-Empty:\r\n\r\n");
+        assert_eq!(&*field.unwrap().value, b"");
+
+        let (field, consumed) = complete_field(b"\r\nbody");
+        assert!(field.is_none());
+        assert_eq!(consumed, 2);
+    }
+
+    #[test]
+    fn joins_folded_lines() {
+        let (field, consumed) = complete_field(b"X-Folded: a\r\n  b \r\n\tc\r\n\r\n");
+        assert_eq!(&*field.unwrap().value, b"a b c");
+        assert_eq!(consumed, 23);
+    }
+
+    #[test]
+    fn field_is_partial_until_the_next_line_starts() {
+        // Only the next line tells whether this one is folded.
+        assert!(matches!(header_field(b"Host: a\r\n"), Ok(Status::Partial)));
+        assert!(matches!(header_field(b"Host: a"), Ok(Status::Partial)));
+        assert!(matches!(header_field(b"Ho"), Ok(Status::Partial)));
+        assert!(matches!(header_field(b"\r"), Ok(Status::Partial)));
+    }
+
+    #[test]
+    fn malformed_fields_say_where() {
+        for (input, offset, expected) in [
+            (&b"Host localhost\r\n\r\n"[..], 4, "':' after the field name"),
+            (b"Host : localhost\r\n\r\n", 4, "':' after the field name"),
+            (b" Host: localhost\r\n\r\n", 0, "a field name"),
+            (b"Host: a\nX-Smuggled: b\r\n\r\n", 7, "CRLF after the field value"),
+            (b"Host: a\rb\r\n\r\n", 7, "CRLF after the field value"),
+            (b"Host: a\0b\r\n\r\n", 7, "CRLF after the field value"),
+        ] {
+            let error = header_field(input).err().expect("should fail");
+            assert_eq!(error, ParseError { offset, expected }, "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Decoding of the request target.
+
+use std::fmt;
+
+use super::RequestError;
+
+/// Splits an origin-form target like `/path?query` into its decoded path
+/// and query.
+pub fn split(target: &str) -> Result<(String, Query), RequestError> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    Ok((decode(path)?, Query::parse(query)?))
+}
+
+/// Decoded query parameters, in the order they were sent. Repeated keys keep
+/// every value.
+#[derive(Debug, Default, PartialEq)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+impl Query {
+    /// Parses `key=value` pairs separated by '&', with '+' standing for a
+    /// space. A key without '=' gets an empty value.
+    pub fn parse(query: &str) -> Result<Query, RequestError> {
+        let mut pairs = vec![];
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            pairs.push((
+                decode(&key.replace('+', " "))?,
+                decode(&value.replace('+', " "))?,
+            ));
+        }
+        Ok(Query { pairs })
+    }
+
+    /// First value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Every value of `key`, in order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs
+            .iter()
+            .filter(move |(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (key, value) in self.iter() {
+            write!(f, "{:?}: {:?}, ", key, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Values the route's pattern took from the path, e.g. `name` for
+/// `/files/{name}`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Params {
+    values: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn push(&mut self, name: &str, value: &str) {
+        self.values.push((String::from(name), String::from(value)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl fmt::Display for Params {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (name, value) in &self.values {
+            write!(f, "{:?}: {:?}, ", name, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+fn decode(text: &str) -> Result<String, RequestError> {
+    let decoded = percent_decode(text).ok_or(RequestError::InvalidPercentEncoding)?;
+    String::from_utf8(decoded).map_err(|_| RequestError::NonUtf8Target)
+}
+
+/// Undoes `%XX` escapes. Fails on an escape that isn't two hex digits.
+fn percent_decode(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len());
+    let mut bytes = text.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+        let high = hex_value(bytes.next()?)?;
+        let low = hex_value(bytes.next()?)?;
+        decoded.push(high << 4 | low);
+    }
+    Some(decoded)
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(query: &Query) -> Vec<(&str, &str)> {
+        query.iter().collect()
+    }
+
+    #[test]
+    fn splits_path_from_query() {
+        let (path, query) = split("/files/a%20b.txt?x=1&y=%2F").unwrap();
+        assert_eq!(path, "/files/a b.txt");
+        assert_eq!(pairs(&query), [("x", "1"), ("y", "/")]);
+
+        let (path, query) = split("/plain").unwrap();
+        assert_eq!(path, "/plain");
+        assert!(query.is_empty());
+
+        // Only the first '?' starts the query.
+        let (path, query) = split("/a?b=c?d").unwrap();
+        assert_eq!(path, "/a");
+        assert_eq!(pairs(&query), [("b", "c?d")]);
+    }
+
+    #[test]
+    fn decodes_once_only() {
+        let (path, _) = split("/files/%252e%252e/x").unwrap();
+        assert_eq!(path, "/files/%2e%2e/x");
+        let (path, _) = split("/files/%2E%2e%2fx").unwrap();
+        assert_eq!(path, "/files/../x");
+    }
+
+    #[test]
+    fn plus_is_a_space_in_the_query_only() {
+        let (path, query) = split("/a+b?q=c+d&e%2Bf=%2B").unwrap();
+        assert_eq!(path, "/a+b");
+        assert_eq!(pairs(&query), [("q", "c d"), ("e+f", "+")]);
+    }
+
+    #[test]
+    fn keeps_repeated_and_bare_keys() {
+        let (_, query) = split("/?x=1&&flag&x=2&=v").unwrap();
+        assert_eq!(query.get("x"), Some("1"));
+        assert_eq!(query.get_all("x").collect::<Vec<_>>(), ["1", "2"]);
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.get(""), Some("v"));
+        assert_eq!(query.get("missing"), None);
+    }
+
+    #[test]
+    fn refuses_bad_escapes() {
+        for target in ["/%", "/%2", "/%zz", "/?q=%g0", "/%+1"] {
+            assert!(
+                matches!(split(target), Err(RequestError::InvalidPercentEncoding)),
+                "{}",
+                target
+            );
+        }
+        for target in ["/%ff", "/%c3", "/?q=%80"] {
+            assert!(
+                matches!(split(target), Err(RequestError::NonUtf8Target)),
+                "{}",
+                target
+            );
+        }
+    }
+}
@@ -1,92 +1,150 @@
+#![allow(clippy::needless_arbitrary_self_type)]
+
 pub mod builder;
+pub mod status;
+pub mod streaming;
+
+use std::io::Result;
+use std::time::Duration;
 
-use std::io::{Result, Write};
-use std::net::TcpStream;
+use tokio::io::AsyncWriteExt;
 
+use crate::connection::Connection;
 use crate::encoding::types::{ContentEncoding, EncodedContent};
+use crate::header::HeaderMap;
 
-pub enum HttpResponseType {
-    Ok,
-    NotFound,
-    InternalServerError,
-    Created,
-    ServiceUnavailable,
-    Conflict,
+pub use status::HttpResponseType;
+use streaming::StreamingBody;
+
+/// What happens to the connection after the response is written.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HttpConnection {
+    Close,
+    /// Keep the connection open, idling for at most the given duration.
+    KeepAlive(Duration),
 }
 
 pub struct HttpResponse {
     pub response_type: HttpResponseType,
     pub content_type: String,
     pub content_length: usize,
+    pub connection: HttpConnection,
+    /// Extra headers, written after the ones derived from the body and
+    /// connection in the order they were added.
+    pub headers: HeaderMap,
     pub body: EncodedContent,
+    /// Replaces `body` when set.
+    pub stream: Option<StreamingBody>,
+    /// Answers HEAD: the headers describe the body, but it is not sent.
+    pub headers_only: bool,
+    /// Ends a body of unknown length by closing the connection instead of
+    /// chunking it, for clients that predate chunked.
+    pub close_delimited: bool,
 }
 
 impl HttpResponseType {
-    fn to_code(&self) -> u16 {
-        match self {
-            HttpResponseType::Ok => 200,
-            HttpResponseType::NotFound => 404,
-            HttpResponseType::InternalServerError => 500,
-            HttpResponseType::Created => 201,
-            HttpResponseType::ServiceUnavailable => 503,
-            HttpResponseType::Conflict => 409,
-        }
-    }
-
-    fn to_str(&self) -> &str {
-        match self {
-            HttpResponseType::Ok => "OK",
-            HttpResponseType::NotFound => "Not Found",
-            HttpResponseType::InternalServerError => "Internal Server Error",
-            HttpResponseType::Created => "Created",
-            HttpResponseType::ServiceUnavailable => "Service Unavailable",
-            HttpResponseType::Conflict => "Conflict",
-        }
-    }
-
+    // Always our own version: HTTP/1.0 clients read a 1.1 status line fine,
+    // and the headers are kept to what they understand.
     fn to_raw_line(&self) -> String {
-        String::from(format!("HTTP/1.1 {} {}", self.to_code(), self.to_str()))
+        format!("HTTP/1.1 {} {}", self.to_code(), self.to_str())
     }
 }
 
 impl HttpResponse {
     fn as_bytes(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-        result.reserve(self.content_length);
+        let mut result: Vec<u8> = Vec::with_capacity(self.content_length);
 
         
         result.extend_from_slice(self.response_type.to_raw_line().as_bytes());
         result.extend_from_slice(b"\r\n");
         
+        match self.connection {
+            HttpConnection::Close => result.extend_from_slice(b"Connection: close\r\n"),
+            HttpConnection::KeepAlive(timeout) => {
+                result.extend_from_slice(b"Connection: keep-alive\r\n");
+                result.extend_from_slice(b"Keep-Alive: timeout=");
+                result.extend_from_slice(timeout.as_secs().to_string().as_bytes());
+                result.extend_from_slice(b"\r\n");
+            }
+        }
+
+        // Always sent when a body is allowed, the client needs it to find
+        // the end of the response on a persistent connection.
+        match self.stream.as_ref().map(|stream| stream.length()) {
+            _ if !self.response_type.allows_body() => {}
+            Some(None) if self.close_delimited => {}
+            Some(None) => result.extend_from_slice(b"Transfer-Encoding: chunked\r\n"),
+            framing => {
+                let length = framing.flatten().unwrap_or(self.content_length as u64);
+                result.extend_from_slice(b"Content-Length: ");
+                result.extend_from_slice(length.to_string().as_bytes());
+                result.extend_from_slice(b"\r\n");
+            }
+        }
+
         if self.has_body() {
             result.extend_from_slice(b"Content-Type: ");
             result.extend_from_slice(self.content_type.as_bytes());
             result.extend_from_slice(b"\r\n");
-    
-            result.extend_from_slice(b"Content-Length: ");
-            result.extend_from_slice(self.content_length.to_string().as_bytes());
-            result.extend_from_slice(b"\r\n");
 
-            if self.body.encoding_type != ContentEncoding::NoEncoding {
+            if self.encoding_type() != ContentEncoding::NoEncoding {
                 result.extend_from_slice(b"Content-Encoding: ");
-                result.extend_from_slice(self.body.encoding_type.to_str().as_bytes());
+                result.extend_from_slice(self.encoding_type().to_str().as_bytes());
                 result.extend_from_slice(b"\r\n");
             }
         }
 
+        for (name, value) in self.headers.iter() {
+            result.extend_from_slice(name.as_bytes());
+            result.extend_from_slice(b": ");
+            result.extend_from_slice(value.as_bytes());
+            result.extend_from_slice(b"\r\n");
+        }
+
         result.extend_from_slice(b"\r\n");
         // body...
-        if self.has_body() {
+        if self.has_body() && !self.is_streaming() && !self.headers_only {
             result.extend_from_slice(&self.body.buffer);
         }
         result
     }
 
-    pub fn respond(mut stream: TcpStream, response: &HttpResponse) -> Result<()> {
-        stream.write_all(&response.as_bytes())
+    pub(crate) async fn respond(
+        stream: &mut impl Connection,
+        mut response: HttpResponse,
+    ) -> Result<()> {
+        stream.write_all(&response.as_bytes()).await?;
+        // Taking the stream makes the response look bodiless, so ask first.
+        let sends_body = response.has_body() && !response.headers_only;
+        match response.stream.take() {
+            Some(body) if sends_body && response.close_delimited => {
+                body.write_unframed(stream).await
+            }
+            Some(body) if sends_body => body.write_to(stream).await,
+            _ => Ok(()),
+        }
     }
 
+    /// Whether a body goes out with the response. Statuses that can't have
+    /// one drop it along with its headers.
     pub fn has_body(self: &Self) -> bool {
-        return self.body.buffer.len() > 0;
+        self.response_type.allows_body() && (self.is_streaming() || !self.body.buffer.is_empty())
+    }
+
+    /// How the body that goes out is encoded, streamed or not.
+    pub fn encoding_type(&self) -> ContentEncoding {
+        match &self.stream {
+            Some(stream) => stream.encoding_type(),
+            None => self.body.encoding_type,
+        }
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Whether the body's length is only known once it has been sent.
+    pub fn has_unknown_length(&self) -> bool {
+        self.has_body() && self.stream.as_ref().is_some_and(|stream| stream.length().is_none())
     }
 }
@@ -1,15 +1,27 @@
-use std::io::Result;
+#![allow(clippy::needless_arbitrary_self_type)]
+
+use std::io::{Error, ErrorKind, Result};
+use std::time::Duration;
 
 use crate::encoding::types::ContentEncoding;
 use crate::encoding::types::EncodedContent;
 
+use crate::header::{self, HeaderMap};
+
+use super::streaming::StreamingBody;
+use super::HttpConnection;
 use super::HttpResponse;
 use super::HttpResponseType;
 
 pub struct HttpResponseBuilder {
     response_type: HttpResponseType,
     content_type: Option<String>,
+    connection: HttpConnection,
+    headers: HeaderMap,
     body: Option<EncodedContent>,
+    stream: Option<StreamingBody>,
+    headers_only: bool,
+    close_delimited: bool,
 }
 
 impl HttpResponseBuilder {
@@ -17,7 +29,12 @@ impl HttpResponseBuilder {
         HttpResponseBuilder {
             response_type,
             content_type: None,
+            connection: HttpConnection::Close,
+            headers: HeaderMap::new(),
             body: None,
+            stream: None,
+            headers_only: false,
+            close_delimited: false,
         }
     }
 
@@ -25,7 +42,12 @@ impl HttpResponseBuilder {
         HttpResponseBuilder {
             response_type: response.response_type,
             content_type: Some(response.content_type),
-            body: Some(response.body)
+            connection: response.connection,
+            headers: response.headers,
+            body: Some(response.body),
+            stream: response.stream,
+            headers_only: response.headers_only,
+            close_delimited: response.close_delimited,
         }
     }
 
@@ -34,13 +56,64 @@ impl HttpResponseBuilder {
         self
     }
 
+    pub fn connection(mut self, connection: HttpConnection) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    pub fn retry_after(self, retry_after: Duration) -> Self {
+        self.set_header("Retry-After", &retry_after.as_secs().to_string())
+            .expect("Retry-After is always valid")
+    }
+
+    /// Adds a header, keeping any already set under the same name.
+    pub fn header(mut self, name: &str, value: &str) -> Result<Self> {
+        validate_custom(name, value)?;
+        self.headers.append(name, value);
+        Ok(self)
+    }
+
+    /// Sets a header, replacing any already set under the same name.
+    pub fn set_header(mut self, name: &str, value: &str) -> Result<Self> {
+        validate_custom(name, value)?;
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    pub fn remove_header(mut self, name: &str) -> Self {
+        self.headers.remove(name);
+        self
+    }
+
     pub fn body(mut self: Self, body: EncodedContent) -> Self {
         self.body = Some(body);
         self
     }
 
+    /// Streams the body instead of sending `body`.
+    pub fn stream(mut self, stream: StreamingBody) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+
+    /// Sends the headers of the body without the body itself, as HEAD wants.
+    pub fn headers_only(mut self) -> Self {
+        self.headers_only = true;
+        self
+    }
+
+    /// Ends a streamed body of unknown length by closing the connection
+    /// rather than chunking it.
+    pub fn close_delimited(mut self) -> Self {
+        self.close_delimited = true;
+        self
+    }
+
+    /// Encodes the body, or the streamed body when there is one.
     pub fn encode_body(mut self: Self, encoding_type: ContentEncoding) -> Result<Self> {
-        if !self.body.is_none() {
+        if let Some(stream) = self.stream.take() {
+            self.stream = Some(stream.encode(encoding_type)?);
+        } else if self.body.is_some() {
             self.body = Some(self.body.unwrap().encode(encoding_type)?);
         }
         Ok(self)
@@ -53,9 +126,111 @@ impl HttpResponseBuilder {
             content_length: self
                 .body
                 .as_ref()
-                .and_then(|c| Some(c.buffer.len()))
+                .map(|c| c.buffer.len())
                 .unwrap_or(0),
+            connection: self.connection,
+            headers: self.headers,
             body: self.body.unwrap_or_default(),
+            stream: self.stream,
+            headers_only: self.headers_only,
+            close_delimited: self.close_delimited,
+        }
+    }
+}
+
+/// Headers the response writes by itself from its body and connection.
+const MANAGED_HEADERS: [&str; 6] = [
+    "content-length",
+    "content-type",
+    "content-encoding",
+    "transfer-encoding",
+    "connection",
+    "keep-alive",
+];
+
+fn validate_custom(name: &str, value: &str) -> Result<()> {
+    header::validate(name, value)?;
+    if MANAGED_HEADERS
+        .iter()
+        .any(|managed| managed.eq_ignore_ascii_case(name))
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is set by the response itself", name),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(builder: HttpResponseBuilder) -> String {
+        String::from_utf8(builder.build().as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn header_refuses_line_breaks() {
+        for value in ["a\r\nSet-Cookie: session=stolen", "a\nb", "a\rb", "a\0b"] {
+            let builder = HttpResponseBuilder::new(HttpResponseType::Ok);
+            assert!(builder.header("X-Value", value).is_err(), "{:?}", value);
+            let builder = HttpResponseBuilder::new(HttpResponseType::Ok);
+            assert!(builder.set_header("X-Value", value).is_err(), "{:?}", value);
         }
+        let builder = HttpResponseBuilder::new(HttpResponseType::Ok);
+        assert!(builder.header("X-Name\r\nSet-Cookie", "value").is_err());
+    }
+
+    #[test]
+    fn header_refuses_framing_headers() {
+        for name in ["Content-Length", "transfer-encoding", "CONNECTION", "Content-Type"] {
+            let builder = HttpResponseBuilder::new(HttpResponseType::Ok);
+            assert!(builder.header(name, "1").is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn headers_are_written_after_the_managed_ones() {
+        let builder = HttpResponseBuilder::new(HttpResponseType::Ok)
+            .header("Set-Cookie", "a=1")
+            .unwrap()
+            .header("Set-Cookie", "b=2")
+            .unwrap()
+            .set_header("X-Id", "1")
+            .unwrap()
+            .set_header("x-id", "2")
+            .unwrap();
+
+        let response = written(builder);
+        let lines: Vec<&str> = response.split("\r\n").collect();
+        assert_eq!(lines[0], "HTTP/1.1 200 OK");
+        let custom = &lines[lines.len() - 5..];
+        assert_eq!(custom, ["Set-Cookie: a=1", "Set-Cookie: b=2", "x-id: 2", "", ""]);
+    }
+
+    #[test]
+    fn bodiless_statuses_drop_the_body_and_its_headers() {
+        for status in [
+            HttpResponseType::SwitchingProtocols,
+            HttpResponseType::NoContent,
+            HttpResponseType::NotModified,
+        ] {
+            let builder = HttpResponseBuilder::new(status.clone())
+                .content_type(String::from("text/html"))
+                .body(EncodedContent::from(b"<p>dropped</p>".to_vec()));
+
+            let response = written(builder);
+            assert!(response.ends_with("\r\n\r\n"), "{:?}: {}", status, response);
+            assert!(!response.contains("dropped"), "{:?}: {}", status, response);
+            assert!(!response.contains("Content-Length"), "{:?}: {}", status, response);
+            assert!(!response.contains("Content-Type"), "{:?}: {}", status, response);
+        }
+    }
+
+    #[test]
+    fn empty_bodies_still_get_a_length() {
+        let response = written(HttpResponseBuilder::new(HttpResponseType::Ok));
+        assert!(response.contains("\r\nContent-Length: 0\r\n"), "{}", response);
     }
 }
@@ -0,0 +1,176 @@
+use std::io::{Error, ErrorKind, Result};
+
+/// Status code and reason phrase outside the standard table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomStatus {
+    code: u16,
+    reason: String,
+}
+
+impl CustomStatus {
+    /// `code` has to be in one of the classes RFC 9110 defines, 100 to 599,
+    /// and `reason` has to fit on the status line.
+    pub fn new(code: u16, reason: &str) -> Result<CustomStatus> {
+        if !(100..=599).contains(&code) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("status code must be between 100 and 599: {}", code),
+            ));
+        }
+        if reason.bytes().any(|byte| matches!(byte, b'\r' | b'\n' | b'\0')) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid reason phrase: {:?}", reason),
+            ));
+        }
+        Ok(CustomStatus {
+            code,
+            reason: String::from(reason),
+        })
+    }
+}
+
+macro_rules! status_table {
+    ($($variant:ident => ($code:literal, $reason:literal),)*) => {
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum HttpResponseType {
+            $($variant,)*
+            Custom(CustomStatus),
+        }
+
+        impl HttpResponseType {
+            pub fn to_code(&self) -> u16 {
+                match self {
+                    $(HttpResponseType::$variant => $code,)*
+                    HttpResponseType::Custom(status) => status.code,
+                }
+            }
+
+            pub fn to_str(&self) -> &str {
+                match self {
+                    $(HttpResponseType::$variant => $reason,)*
+                    HttpResponseType::Custom(status) => &status.reason,
+                }
+            }
+        }
+    };
+}
+
+status_table! {
+    Continue => (100, "Continue"),
+    SwitchingProtocols => (101, "Switching Protocols"),
+    Processing => (102, "Processing"),
+    EarlyHints => (103, "Early Hints"),
+    Ok => (200, "OK"),
+    Created => (201, "Created"),
+    Accepted => (202, "Accepted"),
+    NonAuthoritativeInformation => (203, "Non-Authoritative Information"),
+    NoContent => (204, "No Content"),
+    ResetContent => (205, "Reset Content"),
+    PartialContent => (206, "Partial Content"),
+    MultiStatus => (207, "Multi-Status"),
+    AlreadyReported => (208, "Already Reported"),
+    ImUsed => (226, "IM Used"),
+    MultipleChoices => (300, "Multiple Choices"),
+    MovedPermanently => (301, "Moved Permanently"),
+    Found => (302, "Found"),
+    SeeOther => (303, "See Other"),
+    NotModified => (304, "Not Modified"),
+    UseProxy => (305, "Use Proxy"),
+    TemporaryRedirect => (307, "Temporary Redirect"),
+    PermanentRedirect => (308, "Permanent Redirect"),
+    BadRequest => (400, "Bad Request"),
+    Unauthorized => (401, "Unauthorized"),
+    PaymentRequired => (402, "Payment Required"),
+    Forbidden => (403, "Forbidden"),
+    NotFound => (404, "Not Found"),
+    MethodNotAllowed => (405, "Method Not Allowed"),
+    NotAcceptable => (406, "Not Acceptable"),
+    ProxyAuthenticationRequired => (407, "Proxy Authentication Required"),
+    RequestTimeout => (408, "Request Timeout"),
+    Conflict => (409, "Conflict"),
+    Gone => (410, "Gone"),
+    LengthRequired => (411, "Length Required"),
+    PreconditionFailed => (412, "Precondition Failed"),
+    PayloadTooLarge => (413, "Payload Too Large"),
+    UriTooLong => (414, "URI Too Long"),
+    UnsupportedMediaType => (415, "Unsupported Media Type"),
+    RangeNotSatisfiable => (416, "Range Not Satisfiable"),
+    ExpectationFailed => (417, "Expectation Failed"),
+    ImATeapot => (418, "I'm a teapot"),
+    MisdirectedRequest => (421, "Misdirected Request"),
+    UnprocessableContent => (422, "Unprocessable Content"),
+    Locked => (423, "Locked"),
+    FailedDependency => (424, "Failed Dependency"),
+    TooEarly => (425, "Too Early"),
+    UpgradeRequired => (426, "Upgrade Required"),
+    PreconditionRequired => (428, "Precondition Required"),
+    TooManyRequests => (429, "Too Many Requests"),
+    RequestHeaderFieldsTooLarge => (431, "Request Header Fields Too Large"),
+    UnavailableForLegalReasons => (451, "Unavailable For Legal Reasons"),
+    InternalServerError => (500, "Internal Server Error"),
+    NotImplemented => (501, "Not Implemented"),
+    BadGateway => (502, "Bad Gateway"),
+    ServiceUnavailable => (503, "Service Unavailable"),
+    GatewayTimeout => (504, "Gateway Timeout"),
+    HttpVersionNotSupported => (505, "HTTP Version Not Supported"),
+    VariantAlsoNegotiates => (506, "Variant Also Negotiates"),
+    InsufficientStorage => (507, "Insufficient Storage"),
+    LoopDetected => (508, "Loop Detected"),
+    NotExtended => (510, "Not Extended"),
+    NetworkAuthenticationRequired => (511, "Network Authentication Required"),
+}
+
+impl HttpResponseType {
+    /// Informational responses, 204 and 304 never carry a body.
+    pub fn allows_body(&self) -> bool {
+        let code = self.to_code();
+        !((100..200).contains(&code) || code == 204 || code == 304)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_status_takes_codes_of_the_defined_classes() {
+        for code in [100, 299, 599] {
+            let status = HttpResponseType::Custom(CustomStatus::new(code, "Custom").unwrap());
+            assert_eq!(status.to_code(), code);
+            assert_eq!(status.to_str(), "Custom");
+        }
+        for code in [0, 99, 600, 999, 1000] {
+            assert!(CustomStatus::new(code, "Custom").is_err(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn custom_status_refuses_reasons_that_break_the_status_line() {
+        for reason in ["Bad\r\nSet-Cookie: a=1", "a\nb", "a\rb", "a\0b"] {
+            assert!(CustomStatus::new(299, reason).is_err(), "{:?}", reason);
+        }
+        assert!(CustomStatus::new(299, "").is_ok());
+    }
+
+    #[test]
+    fn bodies_are_only_allowed_where_the_status_has_one() {
+        for status in [
+            HttpResponseType::Continue,
+            HttpResponseType::EarlyHints,
+            HttpResponseType::NoContent,
+            HttpResponseType::NotModified,
+            HttpResponseType::Custom(CustomStatus::new(199, "Custom").unwrap()),
+        ] {
+            assert!(!status.allows_body(), "{:?}", status);
+        }
+        for status in [
+            HttpResponseType::Ok,
+            HttpResponseType::ResetContent,
+            HttpResponseType::NotFound,
+            HttpResponseType::Custom(CustomStatus::new(299, "Custom").unwrap()),
+        ] {
+            assert!(status.allows_body(), "{:?}", status);
+        }
+    }
+}
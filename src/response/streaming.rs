@@ -0,0 +1,239 @@
+use std::fs::File;
+use std::future::poll_fn;
+use std::io::{Error, ErrorKind, Result, SeekFrom};
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::connection::{prefetch, Connection};
+use crate::encoding::gzip::GzipReader;
+use crate::encoding::types::ContentEncoding;
+
+/// Size of the buffer each chunk is read into.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Most bytes handed to a single zero-copy call.
+const SEND_FILE_SIZE: u64 = 1024 * 1024;
+
+enum Source {
+    Reader(Box<dyn AsyncRead + Send + Unpin>, Option<u64>),
+    File(File, u64),
+}
+
+/// Response body that is read from a source while the response is written,
+/// so it never has to be held in memory as a whole.
+pub struct StreamingBody {
+    source: Source,
+    encoding_type: ContentEncoding,
+}
+
+impl StreamingBody {
+    /// Streams the body from `reader`. With an unknown `length` the body is
+    /// sent with `Transfer-Encoding: chunked`.
+    pub fn new(reader: impl AsyncRead + Send + Unpin + 'static, length: Option<u64>) -> Self {
+        StreamingBody {
+            source: Source::Reader(Box::new(reader), length),
+            encoding_type: ContentEncoding::NoEncoding,
+        }
+    }
+
+    /// Streams the first `length` bytes of `file`, handing them from the file
+    /// to the socket directly where the platform allows it.
+    pub fn from_file(file: File, length: u64) -> Self {
+        StreamingBody {
+            source: Source::File(file, length),
+            encoding_type: ContentEncoding::NoEncoding,
+        }
+    }
+
+    pub fn length(&self) -> Option<u64> {
+        match self.source {
+            Source::Reader(_, length) => length,
+            Source::File(_, length) => Some(length),
+        }
+    }
+
+    pub fn encoding_type(&self) -> ContentEncoding {
+        self.encoding_type
+    }
+
+    /// Encodes the body while it is streamed. Its length is only known once
+    /// it has been sent, so it goes out chunked. A streamed body can't be
+    /// decoded again.
+    pub fn encode(self, encoding_type: ContentEncoding) -> Result<StreamingBody> {
+        if self.encoding_type == encoding_type {
+            return Ok(self);
+        }
+        if self.encoding_type != ContentEncoding::NoEncoding {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "streamed bodies can't be re-encoded",
+            ));
+        }
+        let reader: Box<dyn AsyncRead + Send + Unpin> = match self.source {
+            Source::Reader(reader, _) => reader,
+            Source::File(file, length) => Box::new(tokio::fs::File::from_std(file).take(length)),
+        };
+        let reader = match encoding_type {
+            ContentEncoding::NoEncoding => reader,
+            ContentEncoding::Gzip => Box::new(GzipReader::new(reader)),
+        };
+        Ok(StreamingBody {
+            source: Source::Reader(reader, None),
+            encoding_type,
+        })
+    }
+
+    pub async fn write_to(self, stream: &mut impl Connection) -> Result<()> {
+        match self.source {
+            Source::Reader(reader, Some(length)) => copy_exact(reader, length, stream).await,
+            Source::Reader(reader, None) => write_chunked(reader, stream).await,
+            Source::File(file, length) => write_file(file, length, stream).await,
+        }
+    }
+
+    /// Writes the body as is, even when its length is unknown. The reader can
+    /// then only tell where it ends by the connection closing.
+    pub async fn write_unframed(self, stream: &mut impl Connection) -> Result<()> {
+        match self.source {
+            Source::Reader(mut reader, None) => {
+                tokio::io::copy(&mut reader, stream).await?;
+                Ok(())
+            }
+            source => {
+                StreamingBody {
+                    source,
+                    encoding_type: self.encoding_type,
+                }
+                .write_to(stream)
+                .await
+            }
+        }
+    }
+}
+
+async fn write_file(file: File, length: u64, stream: &mut impl Connection) -> Result<()> {
+    // Zero-copy calls run on the worker thread, where waiting for the disk
+    // would stall every connection sharing it. Each piece is read into the
+    // page cache on the blocking pool first, so the call finds it there.
+    let readahead = Arc::new(file.try_clone()?);
+    let mut offset: u64 = 0;
+    while offset < length {
+        let count = (length - offset).min(SEND_FILE_SIZE) as usize;
+        let (ahead, at) = (Arc::clone(&readahead), offset);
+        tokio::task::spawn_blocking(move || prefetch(&ahead, at, count)).await?;
+        match poll_fn(|cx| stream.poll_send_file(cx, &file, &mut offset, count)).await {
+            // File got shorter, let the copy below report it.
+            Some(Ok(0)) => break,
+            Some(Ok(_)) => {}
+            // Not every file can be sent this way, copy it instead.
+            Some(Err(e))
+                if offset == 0
+                    && matches!(e.kind(), ErrorKind::InvalidInput | ErrorKind::Unsupported) =>
+            {
+                break
+            }
+            Some(Err(e)) => return Err(e),
+            None => break,
+        }
+    }
+    if offset == length {
+        return Ok(());
+    }
+
+    let mut file = tokio::fs::File::from_std(file);
+    file.seek(SeekFrom::Start(offset)).await?;
+    copy_exact(Box::new(file), length - offset, stream).await
+}
+
+async fn copy_exact(
+    reader: Box<dyn AsyncRead + Send + Unpin>,
+    length: u64,
+    stream: &mut (impl AsyncWrite + Unpin),
+) -> Result<()> {
+    let copied = tokio::io::copy(&mut reader.take(length), stream).await?;
+    if copied < length {
+        // Content-Length is already out, the client can only notice through
+        // the connection closing.
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("body ended after {} of {} bytes", copied, length),
+        ));
+    }
+    Ok(())
+}
+
+async fn write_chunked(
+    mut reader: Box<dyn AsyncRead + Send + Unpin>,
+    stream: &mut (impl AsyncWrite + Unpin),
+) -> Result<()> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        stream
+            .write_all(format!("{:x}\r\n", read).as_bytes())
+            .await?;
+        stream.write_all(&buffer[..read]).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+    stream.write_all(b"0\r\n\r\n").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+
+    use tokio::io::{duplex, DuplexStream};
+    use tokio::net::{TcpListener, TcpStream};
+
+    // Can't take a file, so bodies are copied through user space.
+    impl Connection for DuplexStream {}
+
+    /// Writes `body` to `writer` and returns what comes out at `reader`.
+    async fn sent(
+        body: StreamingBody,
+        mut writer: impl Connection,
+        mut reader: impl AsyncRead + Unpin,
+    ) -> Vec<u8> {
+        let write = async move {
+            body.write_to(&mut writer).await.unwrap();
+            writer.shutdown().await.unwrap();
+        };
+        let mut received = vec![];
+        let (_, read) = tokio::join!(write, reader.read_to_end(&mut received));
+        read.unwrap();
+        received
+    }
+
+    #[tokio::test]
+    async fn buffered_copy_sends_what_sendfile_does() {
+        // Several zero-copy calls long, with the end of the file left out.
+        let contents: Vec<u8> = (0..3_000_000u32).map(|n| (n * 7 % 251) as u8).collect();
+        let length = contents.len() as u64 - 1000;
+        let path = env::temp_dir().join(format!("http-server-streaming-{}", process::id()));
+        File::create(&path).unwrap().write_all(&contents).unwrap();
+
+        let (client, server) = duplex(64 * 1024);
+        let body = StreamingBody::from_file(File::open(&path).unwrap(), length);
+        let copied = sent(body, client, server).await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let body = StreamingBody::from_file(File::open(&path).unwrap(), length);
+        let zero_copied = sent(body, server, client).await;
+
+        fs::remove_file(path).unwrap();
+        assert_eq!(copied.len() as u64, length);
+        assert!(copied == contents[..length as usize]);
+        assert!(zero_copied == copied);
+    }
+}
@@ -1,54 +1,216 @@
 mod files;
+mod handler;
+mod middleware;
+mod router;
 
+use crate::connection::{Connection, FlushBeforeRead, WriteTimeout};
 use crate::encoding::types::EncodedContent;
 use crate::request;
 use crate::response;
 use crate::server;
 
-use std::io::Result;
-use std::net::TcpStream;
+use std::io::{Error, ErrorKind, Result};
 use std::sync::Arc;
+use std::time::Duration;
 
-use request::HttpRequest;
-use response::{builder::HttpResponseBuilder, HttpResponse, HttpResponseType};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::time::timeout;
+
+use request::{HttpRequest, HttpRequestType, HttpVersion, RequestError};
+use response::{builder::HttpResponseBuilder, HttpConnection, HttpResponse, HttpResponseType};
 use server::ServerContext;
 
-fn route(request: HttpRequest) -> HttpResponse {
-    if request.path.starts_with("/files/") {
-        return files::handle(request);
-    } else if request.path.starts_with("/echo/") {
-        let to_echo = &request.path["/echo/".len()..];
-        return HttpResponseBuilder::new(HttpResponseType::Ok)
-            .body(EncodedContent::from(String::from(to_echo).into_bytes()))
-            .build();
-    } else if request.path.starts_with("/user-agent") {
-        return HttpResponseBuilder::new(HttpResponseType::Ok)
-            .body(EncodedContent::from(request.header.user_agent.into_bytes()))
-            .build();
-    } else if &request.path == "/" {
-        return HttpResponseBuilder::new(HttpResponseType::Ok)
-            .body(EncodedContent::from(String::from("").into_bytes()))
-            .build();
-    } else {
-        return HttpResponseBuilder::new(HttpResponseType::NotFound).build();
-    }
+pub use handler::{Handler, Middleware, ResponseFuture, Stack};
+pub use middleware::{EncodingNegotiation, RequestLog};
+pub use router::Router;
+
+/// Most of a rejected client's request that is read off before closing.
+const MAX_UNREAD_ON_REJECT: u64 = 64 * 1024;
+
+/// The routes the server comes with, behind logging and encoding
+/// negotiation.
+pub fn service() -> Stack {
+    Stack::new(routes())
+        .with(RequestLog)
+        .with(EncodingNegotiation)
 }
 
-pub fn handle_connection(stream: TcpStream, server_context: Arc<ServerContext>) -> Result<()> {
-    let request = HttpRequest::read_from_stream(&stream, server_context)?;
-    println!("request: {}", request);
+/// The routes the server comes with.
+pub fn routes() -> Router {
+    Router::new()
+        .route(HttpRequestType::Get, "/", root)
+        .route(HttpRequestType::Get, "/echo/{*rest}", echo)
+        .route(HttpRequestType::Get, "/user-agent", user_agent)
+        .route(HttpRequestType::Get, "/files/{*path}", files::handle_get)
+        .route(HttpRequestType::Post, "/files/{*path}", files::handle_post)
+}
+
+async fn echo(request: HttpRequest) -> HttpResponse {
+    let to_echo = request.params.get("rest").unwrap_or_default();
+    HttpResponseBuilder::new(HttpResponseType::Ok)
+        .body(EncodedContent::from(String::from(to_echo).into_bytes()))
+        .build()
+}
 
-    let requested_encodings = request.header.accept_encoding.clone();
-    let mut response = route(request);
+async fn user_agent(request: HttpRequest) -> HttpResponse {
+    HttpResponseBuilder::new(HttpResponseType::Ok)
+        .body(EncodedContent::from(request.header.user_agent().as_bytes().to_vec()))
+        .build()
+}
+
+async fn root(_request: HttpRequest) -> HttpResponse {
+    HttpResponseBuilder::new(HttpResponseType::Ok)
+        .body(EncodedContent::from(String::from("").into_bytes()))
+        .build()
+}
 
-    // Match response's encoding with request's.
-    if response.has_body() && !requested_encodings.contains(&response.body.encoding_type) {
-        let valid_encoding = requested_encodings.iter().next().unwrap().clone();
-        // Reencode if they differ
-        // println!("requested encoding {:?}", requested_encoding);
+pub(crate) async fn handle_connection<S>(stream: S, server_context: Arc<ServerContext>) -> Result<()>
+where
+    S: Connection,
+{
+    // Pipelined requests may arrive in a single read, so the reader has to
+    // outlive each request to keep whatever was buffered past it. Requests
+    // are read and answered in turn, so both sides can share the stream.
+    let mut stream = BufReader::new(FlushBeforeRead::new(stream));
+    let mut shutdown = server_context.shutdown.clone();
+    loop {
+        // Wait for the next request to start. A request that has started is
+        // always seen through, but an idle connection is dropped on shutdown.
+        // Reading goes first, so a pipelined request already buffered isn't
+        // dropped when shutdown happens to start at the same time.
+        let idle_result = tokio::select! {
+            biased;
+            filled = timeout(server_context.keep_alive_timeout, stream.fill_buf()) => {
+                filled.map(|result| result.map(|buffer| buffer.is_empty()))
+            }
+            _ = shutdown.wait_for(|stopping| *stopping) => return Ok(()),
+        };
+        match idle_result {
+            Ok(Ok(false)) => {}
+            // Client hung up between requests, nothing to answer.
+            Ok(Ok(true)) => return Ok(()),
+            Ok(Err(e)) if is_connection_gone(&e) => return Ok(()),
+            Ok(Err(e)) => return Err(e),
+            // Went idle for too long.
+            Err(_) => return Ok(()),
+        }
+
+        let request =
+            match HttpRequest::read_from_stream(&mut stream, Arc::clone(&server_context)).await {
+                Ok(request) => request,
+                Err(e) if is_connection_gone(&e) => return Ok(()),
+                Err(e) => {
+                    // The rest of the stream can't be trusted, so answer if the
+                    // client deserves to know why and close either way.
+                    if let Some(response) = error_response(&e) {
+                        println!("Rejecting request: {}", e);
+                        write_response(&mut stream, response, true, &server_context).await?;
+                        return Ok(());
+                    }
+                    return Err(e);
+                }
+            };
+        let mut keep_alive = request.keep_alive() && !*shutdown.borrow();
+        let version = request.version;
+        let mut response = server_context.handler.handle(request).await;
+
+        // HTTP/1.0 has no chunked coding, so a body of unknown length can
+        // only end with the connection.
+        if version == HttpVersion::Http10 && response.has_unknown_length() {
+            keep_alive = false;
+            response = HttpResponseBuilder::from(response)
+                .close_delimited()
+                .build();
+        }
+
+        let connection = if keep_alive {
+            HttpConnection::KeepAlive(server_context.keep_alive_timeout)
+        } else {
+            HttpConnection::Close
+        };
         response = HttpResponseBuilder::from(response)
-            .encode_body(valid_encoding)?
+            .connection(connection)
             .build();
+        // Requests are answered one after another, so responses go out in
+        // the order they were received. Only flush once no pipelined
+        // request is waiting, letting a burst of responses share a write.
+        // Anything held back here goes out before the next read from the
+        // client, in case what is buffered isn't a whole request.
+        let flush = !keep_alive || stream.buffer().is_empty();
+        write_response(&mut stream, response, flush, &server_context).await?;
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+async fn write_response(
+    writer: &mut impl Connection,
+    response: HttpResponse,
+    flush: bool,
+    server_context: &ServerContext,
+) -> Result<()> {
+    // Bodies can be large, so only a client that stops reading times out.
+    let mut writer = WriteTimeout::new(writer, server_context.write_timeout);
+    HttpResponse::respond(&mut writer, response).await?;
+    if flush {
+        writer.flush().await?;
     }
-    HttpResponse::respond(stream, &response)
+    Ok(())
+}
+
+/// Response for a request that could not be read, if it should get one.
+fn error_response(error: &Error) -> Option<HttpResponse> {
+    let response_type = match RequestError::from_io(error)? {
+        RequestError::HeaderTimeout => HttpResponseType::RequestTimeout,
+        RequestError::BodyTimeout => return None,
+        RequestError::RequestLineTooLong(_) => HttpResponseType::UriTooLong,
+        RequestError::HeaderTooLarge(_) | RequestError::TooManyHeaders(_) => {
+            HttpResponseType::RequestHeaderFieldsTooLarge
+        }
+        RequestError::BodyTooLarge(..) => HttpResponseType::PayloadTooLarge,
+        RequestError::ConflictingFraming
+        | RequestError::RepeatedChunked
+        | RequestError::MalformedChunk(_)
+        | RequestError::MalformedRequestLine(_)
+        | RequestError::NonUtf8Target
+        | RequestError::MalformedHeader(_)
+        | RequestError::InvalidContentLength(_)
+        | RequestError::InvalidPercentEncoding
+        | RequestError::MissingHost => HttpResponseType::BadRequest,
+        RequestError::UnsupportedVersion(_) => HttpResponseType::HttpVersionNotSupported,
+        RequestError::UnsupportedTransferEncoding(_) => HttpResponseType::NotImplemented,
+    };
+    // Tell the client what was wrong, it may not be obvious from the status.
+    let diagnostic = format!("{}\n", error);
+    Some(
+        HttpResponseBuilder::new(response_type)
+            .body(EncodedContent::from(diagnostic.into_bytes()))
+            .build(),
+    )
+}
+
+/// Answers a connection the server has no capacity for with 503 and closes it.
+pub(crate) async fn reject_connection<S>(mut stream: S, retry_after: Duration) -> Result<()>
+where
+    S: Connection,
+{
+    let response = HttpResponseBuilder::new(HttpResponseType::ServiceUnavailable)
+        .retry_after(retry_after)
+        .build();
+    HttpResponse::respond(&mut stream, response).await?;
+    // Closing with the request still unread resets the connection, which
+    // can throw away the response before the client gets to read it. So the
+    // client is told we're done and what it still sends is read for a bit.
+    stream.shutdown().await?;
+    let mut unread = (&mut stream).take(MAX_UNREAD_ON_REJECT);
+    let _ = timeout(retry_after, tokio::io::copy(&mut unread, &mut tokio::io::sink())).await;
+    Ok(())
+}
+
+fn is_connection_gone(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset
+    )
 }
@@ -2,41 +2,104 @@ use crate::encoding::types::EncodedContent;
 use crate::request;
 use crate::response;
 use crate::response::builder::HttpResponseBuilder;
+use crate::response::streaming::StreamingBody;
 use crate::server;
 
-use request::{HttpRequest, HttpRequestType};
+use request::HttpRequest;
 use response::{HttpResponse, HttpResponseType};
 use server::ServerContext;
-use std::fs;
-use std::fs::File;
-use std::io::Write;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
 
-pub fn handle(request: HttpRequest) -> HttpResponse {
-    let context: &ServerContext = request.context.as_ref();
-    if context.host_files_path.is_none() {
-        return HttpResponseBuilder::new(HttpResponseType::ServiceUnavailable).build();
-    }
+/// Files can only be served once there is a directory to serve them from.
+fn unavailable() -> HttpResponse {
+    HttpResponseBuilder::new(HttpResponseType::ServiceUnavailable).build()
+}
 
-    if request.request_type == HttpRequestType::Get {
-        return handle_get(request);
-    } else if request.request_type == HttpRequestType::Post {
-        return handle_post(request);
-    }
+pub async fn handle_post(request: HttpRequest) -> HttpResponse {
+    run_blocking(write_file, request).await
+}
+
+pub async fn handle_get(request: HttpRequest) -> HttpResponse {
+    run_blocking(open_file, request).await
+}
+
+/// Runs `handler` on the blocking pool, as file system calls would stall
+/// every connection sharing the worker thread.
+async fn run_blocking(handler: fn(HttpRequest) -> HttpResponse, request: HttpRequest) -> HttpResponse {
+    tokio::task::spawn_blocking(move || handler(request))
+        .await
+        .unwrap_or_else(|err| {
+            HttpResponseBuilder::new(HttpResponseType::InternalServerError)
+                .body(EncodedContent::from(format!("Error: {}", err).into_bytes()))
+                .build()
+        })
+}
 
-    HttpResponseBuilder::new(HttpResponseType::NotFound).build()
+/// Maps what follows `/files/` to a path below `directory`, or the status to
+/// answer with when it doesn't name a file there.
+/// `filename` is already percent-decoded, decoding it again would let `%252e`
+/// through as `.`.
+fn resolve(directory: &Path, filename: &str) -> Result<PathBuf, HttpResponseType> {
+    if filename.contains('\0') {
+        return Err(HttpResponseType::BadRequest);
+    }
+    let mut path = directory.to_path_buf();
+    let mut named = false;
+    // Only plain names are pushed, so a leading '/' can't make the path
+    // absolute and nothing climbs out of the directory.
+    for segment in filename.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return Err(HttpResponseType::Forbidden),
+            segment => {
+                path.push(segment);
+                named = true;
+            }
+        }
+    }
+    if !named {
+        return Err(HttpResponseType::NotFound);
+    }
+    Ok(path)
 }
 
-fn handle_post(request: HttpRequest) -> HttpResponse {
-    let context = request.context.as_ref();
-    let filename = &request.path["/files/".len()..];
-    let path = context.host_files_path.as_ref().unwrap().join(filename);
-    if path.exists() {
-        return HttpResponseBuilder::new(HttpResponseType::Conflict).build();
+/// Resolves the symlinks in `path`, which has to exist, and checks that it
+/// still ends up below `directory`.
+fn confine(directory: &Path, path: &Path) -> Result<PathBuf, HttpResponseType> {
+    let root = fs::canonicalize(directory).map_err(|_| HttpResponseType::ServiceUnavailable)?;
+    let canonical = fs::canonicalize(path).map_err(|_| HttpResponseType::NotFound)?;
+    if !canonical.starts_with(root) {
+        return Err(HttpResponseType::Forbidden);
     }
+    Ok(canonical)
+}
+
+fn write_file(request: HttpRequest) -> HttpResponse {
+    let context: &ServerContext = request.context.as_ref();
+    let Some(directory) = context.host_files_path.as_ref() else {
+        return unavailable();
+    };
+    let filename = request.params.get("path").unwrap_or_default();
+    // The file isn't there yet, so it is the directory it goes into that has
+    // to stay below the root.
+    let confined = resolve(directory, filename).and_then(|path| {
+        let parent = confine(directory, path.parent().unwrap_or(directory))?;
+        Ok(parent.join(path.file_name().unwrap_or_default()))
+    });
+    let path = match confined {
+        Ok(path) => path,
+        Err(status) => return HttpResponseBuilder::new(status).build(),
+    };
 
-    let file = File::create(path);
+    // Unlike `File::create`, this won't follow a symlink left at the path.
+    let file = OpenOptions::new().write(true).create_new(true).open(path);
     match file.and_then(|mut file| file.write_all(request.body.as_slice())) {
         Ok(_) => HttpResponseBuilder::new(HttpResponseType::Created).build(),
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            HttpResponseBuilder::new(HttpResponseType::Conflict).build()
+        }
         Err(err) => HttpResponseBuilder::new(HttpResponseType::InternalServerError)
             .body(EncodedContent::from(
                 format!("Error when writing: {}", err).into_bytes(),
@@ -45,21 +108,28 @@ fn handle_post(request: HttpRequest) -> HttpResponse {
     }
 }
 
-fn handle_get(request: HttpRequest) -> HttpResponse {
-    let context = request.context.as_ref();
-    let filename = &request.path["/files/".len()..];
-    let path = context.host_files_path.as_ref().unwrap().join(filename);
-    if !path.exists() {
+fn open_file(request: HttpRequest) -> HttpResponse {
+    let context: &ServerContext = request.context.as_ref();
+    let Some(directory) = context.host_files_path.as_ref() else {
+        return unavailable();
+    };
+    let filename = request.params.get("path").unwrap_or_default();
+    let path = match resolve(directory, filename).and_then(|path| confine(directory, &path)) {
+        Ok(path) => path,
+        Err(status) => return HttpResponseBuilder::new(status).build(),
+    };
+    if !path.is_file() {
         return HttpResponseBuilder::new(HttpResponseType::NotFound).build();
     }
 
-    match fs::read(path) {
-        Ok(contents) => HttpResponseBuilder::new(HttpResponseType::Ok)
+    let opened = File::open(path).and_then(|file| Ok((file.metadata()?.len(), file)));
+    match opened {
+        Ok((length, file)) => HttpResponseBuilder::new(HttpResponseType::Ok)
             .content_type(String::from("application/octet-stream"))
-            .body(EncodedContent::from(contents))
+            .stream(StreamingBody::from_file(file, length))
             .build(),
         Err(err) => {
-            return HttpResponseBuilder::new(HttpResponseType::InternalServerError)
+            HttpResponseBuilder::new(HttpResponseType::InternalServerError)
                 .body(EncodedContent::from(format!("Error: {}", err).into_bytes()))
                 .build()
         }
@@ -0,0 +1,88 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::request::HttpRequest;
+use crate::response::HttpResponse;
+
+/// Response a handler is still working on.
+pub type ResponseFuture<'a> = Pin<Box<dyn Future<Output = HttpResponse> + Send + 'a>>;
+
+/// Answers requests. Async functions from request to response are handlers
+/// too. Handlers run on the connection's task, so blocking work belongs in
+/// `tokio::task::spawn_blocking`.
+pub trait Handler: Send + Sync {
+    fn handle(&self, request: HttpRequest) -> ResponseFuture<'_>;
+}
+
+impl<F, R> Handler for F
+where
+    F: Fn(HttpRequest) -> R + Send + Sync,
+    R: Future<Output = HttpResponse> + Send + 'static,
+{
+    fn handle(&self, request: HttpRequest) -> ResponseFuture<'_> {
+        Box::pin(self(request))
+    }
+}
+
+/// Wraps the rest of a [`Stack`]. It can look at or change the request
+/// before passing it to `next`, answer it without calling `next` at all, and
+/// rewrite the response on the way back.
+pub trait Middleware: Send + Sync {
+    fn handle<'a>(&'a self, request: HttpRequest, next: &'a dyn Handler) -> ResponseFuture<'a>;
+}
+
+/// A handler behind middlewares. Requests pass the middlewares in the order
+/// they were added, responses come back in reverse.
+pub struct Stack {
+    middlewares: Vec<Box<dyn Middleware>>,
+    handler: Box<dyn Handler>,
+}
+
+impl Stack {
+    pub fn new(handler: impl Handler + 'static) -> Stack {
+        Stack {
+            middlewares: vec![],
+            handler: Box::new(handler),
+        }
+    }
+
+    /// Adds `middleware` inside the ones added before it.
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+}
+
+impl Handler for Stack {
+    fn handle(&self, request: HttpRequest) -> ResponseFuture<'_> {
+        Box::pin(async move {
+            Next {
+                middlewares: &self.middlewares,
+                handler: self.handler.as_ref(),
+            }
+            .handle(request)
+            .await
+        })
+    }
+}
+
+/// What is left of a stack below a middleware.
+struct Next<'a> {
+    middlewares: &'a [Box<dyn Middleware>],
+    handler: &'a dyn Handler,
+}
+
+impl Handler for Next<'_> {
+    fn handle(&self, request: HttpRequest) -> ResponseFuture<'_> {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => Box::pin(async move {
+                let next = Next {
+                    middlewares: rest,
+                    handler: self.handler,
+                };
+                middleware.handle(request, &next).await
+            }),
+            None => self.handler.handle(request),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::encoding::types::EncodedContent;
+use crate::request::HttpRequest;
+use crate::response::builder::HttpResponseBuilder;
+use crate::response::HttpResponseType;
+
+use super::handler::{Handler, Middleware, ResponseFuture};
+
+/// Prints every request that comes in.
+pub struct RequestLog;
+
+impl Middleware for RequestLog {
+    fn handle<'a>(&'a self, request: HttpRequest, next: &'a dyn Handler) -> ResponseFuture<'a> {
+        println!("request: {}", request);
+        next.handle(request)
+    }
+}
+
+/// Re-encodes response bodies the client can't accept as they are into an
+/// encoding it can. Streamed bodies are encoded as they are sent.
+pub struct EncodingNegotiation;
+
+impl Middleware for EncodingNegotiation {
+    fn handle<'a>(&'a self, request: HttpRequest, next: &'a dyn Handler) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            let requested_encodings = request.header.accept_encoding().clone();
+            let response = next.handle(request).await;
+            if !response.has_body() || requested_encodings.contains(&response.encoding_type())
+            {
+                return response;
+            }
+
+            let valid_encoding = *requested_encodings.iter().next().unwrap();
+            match HttpResponseBuilder::from(response).encode_body(valid_encoding) {
+                Ok(builder) => builder.build(),
+                Err(err) => HttpResponseBuilder::new(HttpResponseType::InternalServerError)
+                    .body(EncodedContent::from(
+                        format!("Error when encoding: {}", err).into_bytes(),
+                    ))
+                    .build(),
+            }
+        })
+    }
+}
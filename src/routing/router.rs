@@ -0,0 +1,247 @@
+use std::cmp::Ordering;
+
+use itertools::Itertools;
+
+use crate::request::target::Params;
+use crate::request::{HttpRequest, HttpRequestType};
+use crate::response::builder::HttpResponseBuilder;
+use crate::response::{HttpResponse, HttpResponseType};
+
+use super::handler::{Handler, ResponseFuture};
+
+/// Part of a path pattern, between two '/'.
+#[derive(Debug, PartialEq)]
+enum Segment {
+    /// Matches itself.
+    Static(String),
+    /// `{name}` or `{name:int}`, matches one non-empty segment.
+    Param(String, ParamKind),
+    /// `{*name}`, matches the rest of the path, slashes included.
+    Wildcard(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum ParamKind {
+    Any,
+    /// Decimal digits only.
+    Int,
+}
+
+impl Segment {
+    fn parse(segment: &str, is_last: bool, pattern: &str) -> Segment {
+        let Some(inner) = segment
+            .strip_prefix('{')
+            .and_then(|segment| segment.strip_suffix('}'))
+        else {
+            return Segment::Static(String::from(segment));
+        };
+        if let Some(name) = inner.strip_prefix('*') {
+            assert!(is_last, "wildcard has to come last in {}", pattern);
+            return Segment::Wildcard(String::from(name));
+        }
+        match inner.split_once(':') {
+            None => Segment::Param(String::from(inner), ParamKind::Any),
+            Some((name, "int")) => Segment::Param(String::from(name), ParamKind::Int),
+            Some((_, kind)) => panic!("unknown parameter type {} in {}", kind, pattern),
+        }
+    }
+
+    /// Lower is more specific. A typed parameter goes before an untyped one,
+    /// which would otherwise shadow it depending on registration order.
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Static(_) => 0,
+            Segment::Param(_, ParamKind::Int) => 1,
+            Segment::Param(_, ParamKind::Any) => 2,
+            Segment::Wildcard(_) => 3,
+        }
+    }
+}
+
+impl ParamKind {
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            ParamKind::Any => !value.is_empty(),
+            ParamKind::Int => !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit()),
+        }
+    }
+}
+
+/// One path pattern and the handler each method has on it.
+struct Route {
+    segments: Vec<Segment>,
+    handlers: Vec<(HttpRequestType, Box<dyn Handler>)>,
+}
+
+impl Route {
+    /// Parameters taken from `path`, if it matches.
+    fn matches(&self, path: &str) -> Option<Params> {
+        let mut params = Params::default();
+        let mut remaining = path.strip_prefix('/');
+        for segment in &self.segments {
+            let current = remaining?;
+            if let Segment::Wildcard(name) = segment {
+                params.push(name, current);
+                return Some(params);
+            }
+            let (value, rest) = match current.split_once('/') {
+                Some((value, rest)) => (value, Some(rest)),
+                None => (current, None),
+            };
+            match segment {
+                Segment::Static(expected) if expected == value => {}
+                Segment::Param(name, kind) if kind.accepts(value) => params.push(name, value),
+                _ => return None,
+            }
+            remaining = rest;
+        }
+        remaining.is_none().then_some(params)
+    }
+
+    /// Orders routes matching the same path, static segments first, then
+    /// typed parameters, untyped ones, then wildcards.
+    fn specificity(&self, other: &Route) -> Ordering {
+        let ranks = |route: &Route| route.segments.iter().map(Segment::rank).collect_vec();
+        ranks(self).cmp(&ranks(other))
+    }
+
+    fn handler(&self, method: &HttpRequestType) -> Option<&dyn Handler> {
+        self.handlers
+            .iter()
+            .find(|(registered, _)| registered == method)
+            .map(|(_, handler)| handler.as_ref())
+    }
+
+    /// What the route registers, plus HEAD wherever there is GET and OPTIONS
+    /// everywhere.
+    fn allowed_methods(&self) -> Vec<HttpRequestType> {
+        let mut methods = vec![];
+        for (method, _) in &self.handlers {
+            methods.push(method.clone());
+            if *method == HttpRequestType::Get {
+                methods.push(HttpRequestType::Head);
+            }
+        }
+        methods.push(HttpRequestType::Options);
+        methods
+    }
+}
+
+/// Dispatches requests to the handler registered for their method on the
+/// most specific pattern matching their path.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Registers `handler` for `method` on `pattern`, e.g. `/files/{name}`,
+    /// `/users/{id:int}` or `/echo/{*rest}`. What the braces matched is
+    /// found in the request's `params` under the name inside them.
+    ///
+    /// Panics on a malformed pattern.
+    pub fn route(
+        mut self,
+        method: HttpRequestType,
+        pattern: &str,
+        handler: impl Handler + 'static,
+    ) -> Self {
+        let path = pattern
+            .strip_prefix('/')
+            .unwrap_or_else(|| panic!("pattern has to start with '/': {}", pattern));
+        let count = path.split('/').count();
+        let segments = path
+            .split('/')
+            .enumerate()
+            .map(|(index, segment)| Segment::parse(segment, index + 1 == count, pattern))
+            .collect_vec();
+
+        match self.routes.iter_mut().find(|route| route.segments == segments) {
+            Some(route) => route.handlers.push((method, Box::new(handler))),
+            None => self.routes.push(Route {
+                segments,
+                handlers: vec![(method, Box::new(handler))],
+            }),
+        }
+        self
+    }
+
+    /// Methods outside the common set can't be allowed anywhere unless some
+    /// route registers them.
+    fn is_known(&self, method: &HttpRequestType) -> bool {
+        !matches!(
+            method,
+            HttpRequestType::Connect | HttpRequestType::Trace | HttpRequestType::Extension(_)
+        ) || self
+            .routes
+            .iter()
+            .any(|route| route.handler(method).is_some())
+    }
+}
+
+impl Handler for Router {
+    fn handle(&self, mut request: HttpRequest) -> ResponseFuture<'_> {
+        Box::pin(async move {
+            if !self.is_known(&request.request_type) {
+                return HttpResponseBuilder::new(HttpResponseType::NotImplemented).build();
+            }
+            // `*` asks about the server as a whole.
+            if request.request_type == HttpRequestType::Options && request.path == "*" {
+                return options(self.routes.iter().flat_map(Route::allowed_methods));
+            }
+
+            let matched = self
+                .routes
+                .iter()
+                .filter_map(|route| Some((route, route.matches(&request.path)?)))
+                .sorted_by(|(first, _), (second, _)| first.specificity(second))
+                .collect_vec();
+            if matched.is_empty() {
+                return HttpResponseBuilder::new(HttpResponseType::NotFound).build();
+            }
+            let allowed = || matched.iter().flat_map(|(route, _)| route.allowed_methods());
+            if request.request_type == HttpRequestType::Options {
+                return options(allowed());
+            }
+
+            for (route, params) in &matched {
+                if let Some(handler) = route.handler(&request.request_type) {
+                    request.params = params.clone();
+                    return handler.handle(request).await;
+                }
+                // HEAD is GET without the body, headers and all.
+                if request.request_type == HttpRequestType::Head {
+                    if let Some(handler) = route.handler(&HttpRequestType::Get) {
+                        request.request_type = HttpRequestType::Get;
+                        request.params = params.clone();
+                        return HttpResponseBuilder::from(handler.handle(request).await)
+                            .headers_only()
+                            .build();
+                    }
+                }
+            }
+            HttpResponseBuilder::new(HttpResponseType::MethodNotAllowed)
+                .set_header("Allow", &allow_header(allowed()))
+                .expect("method names are valid header values")
+                .build()
+        })
+    }
+}
+
+fn options(methods: impl Iterator<Item = HttpRequestType>) -> HttpResponse {
+    HttpResponseBuilder::new(HttpResponseType::NoContent)
+        .set_header("Allow", &allow_header(methods))
+        .expect("method names are valid header values")
+        .build()
+}
+
+fn allow_header(methods: impl Iterator<Item = HttpRequestType>) -> String {
+    methods
+        .unique()
+        .map(|method| method.as_str().to_string())
+        .join(", ")
+}
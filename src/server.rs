@@ -1,5 +1,397 @@
-use std::path::PathBuf;
+use std::fs::{self, Permissions};
+use std::future::Future;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{SocketAddr, TcpListener as StdTcpListener};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener as StdUnixListener, UnixStream as StdUnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{watch, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+
+use crate::connection::Connection;
+use crate::routing::{handle_connection, reject_connection, Handler};
+
+/// What overloaded clients are told to wait before retrying.
+const RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Upper bounds on what a client may send, enforced while reading.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    /// Longest request line accepted, in bytes.
+    pub max_request_line: usize,
+    /// Longest single header line accepted, in bytes.
+    pub max_header_size: usize,
+    /// Most header lines accepted in one request.
+    pub max_headers: usize,
+    /// Largest request body accepted, in bytes.
+    pub max_body_size: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_request_line: 8192,
+            max_header_size: 8192,
+            max_headers: 100,
+            max_body_size: 64 * 1024 * 1024,
+        }
+    }
+}
 
 pub struct ServerContext {
-    pub host_files_path: Option<PathBuf>
-}
\ No newline at end of file
+    pub host_files_path: Option<PathBuf>,
+    /// How long an idle keep-alive connection is kept open.
+    pub keep_alive_timeout: Duration,
+    /// How long a client gets to send the request line and headers.
+    pub header_read_timeout: Duration,
+    /// How long sending the request body may stall.
+    pub body_read_timeout: Duration,
+    /// How long writing a response may stall on a client that reads nothing.
+    pub write_timeout: Duration,
+    pub limits: RequestLimits,
+    /// Answers every request that could be read.
+    pub handler: Box<dyn Handler>,
+    /// Flips to `true` once the server starts shutting down.
+    pub shutdown: watch::Receiver<bool>,
+}
+
+/// Sockets bound and settings chosen, waiting for a handler to serve with.
+pub struct Server {
+    sockets: Vec<BoundSocket>,
+    /// Removed again once the server shuts down.
+    unix_socket_paths: Vec<PathBuf>,
+    host_files_path: Option<PathBuf>,
+    keep_alive_timeout: Duration,
+    header_read_timeout: Duration,
+    body_read_timeout: Duration,
+    write_timeout: Duration,
+    limits: RequestLimits,
+    max_connections: u32,
+    drain_timeout: Duration,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            sockets: vec![],
+            unix_socket_paths: vec![],
+            host_files_path: None,
+            keep_alive_timeout: Duration::from_secs(5),
+            header_read_timeout: Duration::from_secs(10),
+            body_read_timeout: Duration::from_secs(60),
+            write_timeout: Duration::from_secs(60),
+            limits: RequestLimits::default(),
+            max_connections: 16384,
+            drain_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Server {
+    /// Server listening nowhere yet.
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    /// Server listening on `address`, given as `host:port`.
+    pub fn bind(address: &str) -> Result<Server> {
+        Server::new().listen(address)
+    }
+
+    /// Also listens on `address`, given as `host:port`.
+    pub fn listen(mut self, address: &str) -> Result<Self> {
+        let listener = StdTcpListener::bind(address)
+            .map_err(|e| Error::new(e.kind(), format!("Failed to bind {}: {}", address, e)))?;
+        println!("Listening on {}", listener.local_addr()?);
+        self.sockets.push(BoundSocket::Tcp(listener));
+        Ok(self)
+    }
+
+    /// Also listens on a Unix socket at `path`, created with permissions
+    /// `mode`. A socket file left behind by a server that is no longer
+    /// running is replaced.
+    pub fn listen_unix(mut self, path: &Path, mode: u32) -> Result<Self> {
+        let listener = bind_unix_socket(path, mode).map_err(|e| {
+            Error::new(e.kind(), format!("Failed to bind {}: {}", path.display(), e))
+        })?;
+        println!("Listening on {}", path.display());
+        self.sockets.push(BoundSocket::Unix(listener));
+        self.unix_socket_paths.push(path.to_path_buf());
+        Ok(self)
+    }
+
+    /// Address of the first TCP socket, e.g. to learn the port picked for
+    /// `host:0`.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.sockets
+            .iter()
+            .find_map(|socket| match socket {
+                BoundSocket::Tcp(listener) => Some(listener.local_addr()),
+                BoundSocket::Unix(_) => None,
+            })
+            .unwrap_or_else(|| Err(Error::new(ErrorKind::NotFound, "not listening on TCP")))
+    }
+
+    /// Directory the `/files/` routes serve from.
+    pub fn files(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.host_files_path = Some(directory.into());
+        self
+    }
+
+    /// How long an idle persistent connection is kept open.
+    pub fn keep_alive_timeout(mut self, keep_alive_timeout: Duration) -> Self {
+        self.keep_alive_timeout = keep_alive_timeout;
+        self
+    }
+
+    /// How long a client gets to send the request line and headers.
+    pub fn header_timeout(mut self, header_timeout: Duration) -> Self {
+        self.header_read_timeout = header_timeout;
+        self
+    }
+
+    /// How long sending the request body may stall. A client that keeps
+    /// sending can take as long as it needs for the whole body.
+    pub fn body_timeout(mut self, body_timeout: Duration) -> Self {
+        self.body_read_timeout = body_timeout;
+        self
+    }
+
+    /// How long writing a response may stall on a client that reads nothing.
+    pub fn write_timeout(mut self, write_timeout: Duration) -> Self {
+        self.write_timeout = write_timeout;
+        self
+    }
+
+    pub fn limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Most connections served at once. Connections beyond that are answered
+    /// with 503 Service Unavailable.
+    pub fn max_connections(mut self, max_connections: u32) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// How long active connections get to finish once shutdown starts.
+    pub fn drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Answers requests with `handler` until SIGTERM or SIGINT arrives, then
+    /// drains the active connections.
+    pub async fn serve(self, handler: impl Handler + 'static) -> Result<()> {
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        self.serve_with_shutdown(handler, async move {
+            tokio::select! {
+                _ = terminate.recv() => {},
+                _ = interrupt.recv() => {},
+            }
+        })
+        .await
+    }
+
+    /// Answers requests with `handler` until `shutdown` completes, then
+    /// drains the active connections.
+    pub async fn serve_with_shutdown(
+        self,
+        handler: impl Handler + 'static,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()> {
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        let server_context = Arc::new(ServerContext {
+            host_files_path: self.host_files_path,
+            keep_alive_timeout: self.keep_alive_timeout,
+            header_read_timeout: self.header_read_timeout,
+            body_read_timeout: self.body_read_timeout,
+            write_timeout: self.write_timeout,
+            limits: self.limits,
+            handler: Box::new(handler),
+            shutdown: shutdown_receiver,
+        });
+        let max_connections = self.max_connections;
+        let connection_slots = Arc::new(Semaphore::new(max_connections as usize));
+
+        println!("Logs from your program will appear here!");
+        let mut accept_loops = JoinSet::new();
+        for socket in self.sockets {
+            accept_loops.spawn(accept_loop(
+                socket.into_listener()?,
+                Arc::clone(&server_context),
+                Arc::clone(&connection_slots),
+            ));
+        }
+
+        shutdown.await;
+        println!("Shutting down, no longer accepting connections");
+        // Accept loops stop on the same signal the connections watch, handing
+        // back the connections they started.
+        let _ = shutdown_sender.send(true);
+        let mut connection_sets = vec![];
+        while let Some(accepted) = accept_loops.join_next().await {
+            connection_sets.push(accepted?);
+        }
+
+        let active: usize = connection_sets.iter().map(JoinSet::len).sum();
+        let drain_timeout = self.drain_timeout;
+        let _ = timeout(drain_timeout, async {
+            for connections in &mut connection_sets {
+                while connections.join_next().await.is_some() {}
+            }
+        })
+        .await;
+        // Whatever is still running after the drain timeout is aborted.
+        let mut remaining = 0;
+        for mut connections in connection_sets {
+            remaining += connections.len();
+            connections.shutdown().await;
+        }
+
+        for path in &self.unix_socket_paths {
+            let _ = fs::remove_file(path);
+        }
+        println!(
+            "Shutdown complete: {} of {} active connections drained, {} cut off after {:?}",
+            active - remaining,
+            active,
+            remaining,
+            drain_timeout
+        );
+        Ok(())
+    }
+}
+
+/// Binds a Unix socket at `path`, replacing a socket file left behind by a
+/// server that is no longer running.
+fn bind_unix_socket(path: &Path, mode: u32) -> Result<StdUnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "path exists and is not a socket",
+            ));
+        }
+        match StdUnixStream::connect(path) {
+            Ok(_) => {
+                return Err(Error::new(
+                    ErrorKind::AddrInUse,
+                    "another server is listening on this socket",
+                ))
+            }
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                println!("Removing stale socket {}", path.display());
+                fs::remove_file(path)?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let listener = StdUnixListener::bind(path)?;
+    fs::set_permissions(path, Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+/// Socket bound before serving starts, so a bad address fails early and
+/// binding needs no runtime.
+enum BoundSocket {
+    Tcp(StdTcpListener),
+    Unix(StdUnixListener),
+}
+
+impl BoundSocket {
+    fn into_listener(self) -> Result<Listener> {
+        match self {
+            BoundSocket::Tcp(listener) => {
+                listener.set_nonblocking(true)?;
+                Ok(Listener::Tcp(TcpListener::from_std(listener)?))
+            }
+            BoundSocket::Unix(listener) => {
+                listener.set_nonblocking(true)?;
+                Ok(Listener::Unix(UnixListener::from_std(listener)?))
+            }
+        }
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    async fn accept(&self) -> Result<Accepted> {
+        match self {
+            Listener::Tcp(listener) => Ok(Accepted::Tcp(listener.accept().await?.0)),
+            Listener::Unix(listener) => Ok(Accepted::Unix(listener.accept().await?.0)),
+        }
+    }
+}
+
+enum Accepted {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+/// Accepts clients until shutdown starts, then hands back the connections
+/// still being served.
+async fn accept_loop(
+    listener: Listener,
+    server_context: Arc<ServerContext>,
+    connection_slots: Arc<Semaphore>,
+) -> JoinSet<()> {
+    let mut connections = JoinSet::new();
+    let mut shutdown = server_context.shutdown.clone();
+    loop {
+        tokio::select! {
+            _ = shutdown.wait_for(|stopping| *stopping) => return connections,
+            // Finished connections are collected as they go, so the set only
+            // holds the ones still running.
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            accepted = listener.accept() => match accepted {
+                Ok(Accepted::Tcp(stream)) => {
+                    spawn_connection(stream, &server_context, &connection_slots, &mut connections)
+                }
+                Ok(Accepted::Unix(stream)) => {
+                    spawn_connection(stream, &server_context, &connection_slots, &mut connections)
+                }
+                Err(e) => println!("Error: {}", e),
+            },
+        }
+    }
+}
+
+fn spawn_connection<S>(
+    stream: S,
+    server_context: &Arc<ServerContext>,
+    connection_slots: &Arc<Semaphore>,
+    connections: &mut JoinSet<()>,
+) where
+    S: Connection + 'static,
+{
+    println!("Accepted new connection");
+    let Ok(slot) = Arc::clone(connection_slots).try_acquire_owned() else {
+        println!("Too many connections, rejecting");
+        connections.spawn(async move {
+            if let Err(e) = reject_connection(stream, RETRY_AFTER).await {
+                println!("Error in connection: {}", e);
+            }
+        });
+        return;
+    };
+    let context = Arc::clone(server_context);
+    connections.spawn(async move {
+        if let Err(e) = handle_connection(stream, context).await {
+            println!("Error in connection: {}", e);
+        }
+        drop(slot);
+    });
+}
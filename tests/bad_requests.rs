@@ -0,0 +1,57 @@
+mod common;
+
+use std::io::{Read, Write};
+
+use pretty_assertions::assert_eq;
+
+use common::TestServer;
+
+#[test]
+fn malformed_requests_get_400_with_a_reason() {
+    let server = TestServer::spawn();
+    let cases: [(&[u8], &str); 5] = [
+        (
+            b"GET /\r\n\r\n",
+            "malformed request line: expected a space after the request target at byte 5\n",
+        ),
+        (
+            b"GET /\xff HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            "request target is not valid UTF-8\n",
+        ),
+        (
+            b"GET / HTTP/1.1\r\nHost localhost\r\n\r\n",
+            "malformed header: expected ':' after the field name at byte 4 in \"Host localhost\"\n",
+        ),
+        (
+            b"POST /echo/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1x\r\n\r\n",
+            "invalid Content-Length: \"1x\"\n",
+        ),
+        (
+            b"GET / HTTP/1.1\r\n\r\n",
+            "HTTP/1.1 requests need exactly one Host header\n",
+        ),
+    ];
+
+    for (request, reason) in cases {
+        let mut stream = server.connect();
+        stream.write_all(request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", head);
+        assert_eq!(body, reason);
+    }
+}
+
+#[test]
+fn unsupported_version_gets_505() {
+    let server = TestServer::spawn();
+    let mut stream = server.connect();
+
+    stream.write_all(b"GET / HTTP/2.0\r\nHost: localhost\r\n\r\n").unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+}
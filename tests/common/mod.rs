@@ -0,0 +1,164 @@
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+use http_server_starter_rust::{routing, Handler, Server};
+
+/// Server running in the test process on a port of its own. It starts
+/// shutting down when dropped.
+pub struct TestServer {
+    pub address: SocketAddr,
+    stop: Option<oneshot::Sender<()>>,
+    serving: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl TestServer {
+    /// Serves the built-in routes with the default settings.
+    pub fn spawn() -> TestServer {
+        TestServer::spawn_with(|server| server)
+    }
+
+    /// Serves the built-in routes, with `configure` applied to the server.
+    pub fn spawn_with(configure: impl FnOnce(Server) -> Server) -> TestServer {
+        TestServer::serve(configure, routing::service())
+    }
+
+    /// Serves `handler`, with `configure` applied to the server.
+    pub fn serve(
+        configure: impl FnOnce(Server) -> Server,
+        handler: impl Handler + 'static,
+    ) -> TestServer {
+        // Connections left open by a test shouldn't hold up its end for long.
+        let server = Server::bind("127.0.0.1:0")
+            .expect("failed to bind")
+            .drain_timeout(Duration::from_secs(1));
+        let server = configure(server);
+        let address = server.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let serving = thread::spawn(move || {
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .enable_all()
+                .build()?
+                .block_on(server.serve_with_shutdown(handler, async move {
+                    let _ = stopped.await;
+                }))
+        });
+        TestServer {
+            address,
+            stop: Some(stop),
+            serving: Some(serving),
+        }
+    }
+
+    pub fn connect(&self) -> TcpStream {
+        TcpStream::connect(self.address).unwrap()
+    }
+
+    /// Sends `request` on a new connection, reads until the server closes it
+    /// and returns the head of the first response, up to and including the
+    /// last header line, and everything after it.
+    pub fn exchange(&self, request: impl AsRef<[u8]>) -> (String, Vec<u8>) {
+        let mut stream = self.connect();
+        stream.write_all(request.as_ref()).unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        let split = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap_or_else(|| panic!("no complete head in {:?}", response));
+        let head = String::from_utf8(response[..split + 2].to_vec()).unwrap();
+        (head, response[split + 4..].to_vec())
+    }
+
+    /// Sends `request` and returns the status line of the response.
+    pub fn status_of(&self, request: &str) -> String {
+        let (head, _) = self.exchange(request);
+        String::from(head.lines().next().unwrap())
+    }
+
+    /// Uploads `body` to `/files/<name>` with the given framing headers and
+    /// returns the status line and body of the response.
+    pub fn upload(&self, name: &str, framing: &str, body: &str) -> (String, String) {
+        let (head, body) = self.exchange(format!(
+            "POST /files/{} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n{}",
+            name, framing, body
+        ));
+        (
+            String::from(head.lines().next().unwrap()),
+            String::from_utf8(body).unwrap(),
+        )
+    }
+
+    /// Starts shutting down, like SIGTERM does.
+    pub fn terminate(&mut self) {
+        self.stop.take();
+    }
+
+    /// Shuts down and waits until the active connections are drained.
+    pub fn wait(mut self) -> io::Result<()> {
+        self.terminate();
+        self.serving.take().unwrap().join().unwrap()
+    }
+}
+
+/// Fresh empty directory for a test to serve files from, removed again with
+/// everything in it when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl From<&TempDir> for PathBuf {
+    fn from(dir: &TempDir) -> PathBuf {
+        dir.path.clone()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+pub fn temp_dir(name: &str) -> TempDir {
+    let path = env::temp_dir().join(format!("http-server-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    TempDir { path }
+}
+
+/// Undoes `Transfer-Encoding: chunked`, checking the framing on the way.
+pub fn dechunk(mut encoded: &[u8]) -> Vec<u8> {
+    let mut decoded = vec![];
+    loop {
+        let line_end = encoded.windows(2).position(|window| window == b"\r\n").unwrap();
+        let size = std::str::from_utf8(&encoded[..line_end]).unwrap();
+        let size = usize::from_str_radix(size, 16).unwrap();
+        encoded = &encoded[line_end + 2..];
+        if size == 0 {
+            assert_eq!(encoded, b"\r\n");
+            return decoded;
+        }
+        decoded.extend_from_slice(&encoded[..size]);
+        assert_eq!(&encoded[size..size + 2], b"\r\n");
+        encoded = &encoded[size + 2..];
+    }
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::fs;
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use pretty_assertions::assert_eq;
+
+use common::{dechunk, temp_dir, TestServer};
+
+#[test]
+fn gzips_bodies_for_clients_that_ask() {
+    let server = TestServer::spawn();
+
+    let (head, body_bytes) = server.exchange(
+        "GET /echo/zipped HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n",
+    );
+    assert!(head.contains("\r\nContent-Encoding: gzip\r\n"), "{}", head);
+
+    let mut body = String::new();
+    GzDecoder::new(body_bytes.as_slice())
+        .read_to_string(&mut body)
+        .unwrap();
+    assert_eq!(body, "zipped");
+}
+
+#[test]
+fn gzips_streamed_files_for_clients_that_ask() {
+    let dir = temp_dir("encoding");
+    let contents = "streamed, ".repeat(10_000);
+    fs::write(dir.join("data.txt"), &contents).unwrap();
+    let server = TestServer::spawn_with(|server| server.files(&dir));
+
+    let (head, body_bytes) = server.exchange(
+        "GET /files/data.txt HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n",
+    );
+    assert!(head.contains("\r\nContent-Encoding: gzip\r\n"), "{}", head);
+    // The compressed length isn't known up front.
+    assert!(head.contains("\r\nTransfer-Encoding: chunked\r\n"), "{}", head);
+
+    let mut body = String::new();
+    GzDecoder::new(dechunk(&body_bytes).as_slice())
+        .read_to_string(&mut body)
+        .unwrap();
+    assert_eq!(body, contents);
+}
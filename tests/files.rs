@@ -0,0 +1,92 @@
+mod common;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pretty_assertions::assert_eq;
+
+use common::{temp_dir, TestServer};
+
+#[test]
+fn serves_file_contents() {
+    let dir = temp_dir("files");
+    fs::write(dir.join("hello.txt"), "hello, world").unwrap();
+    let server = TestServer::spawn_with(|server| server.files(&dir));
+    let mut stream = server.connect();
+
+    stream
+        .write_all(b"GET /files/hello.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(body, "hello, world");
+}
+
+/// Sends a GET for `name` and returns the stream with the head read off.
+fn request_file(server: &TestServer, name: &str) -> TcpStream {
+    let mut stream = server.connect();
+    let request = format!(
+        "GET /files/{} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        name
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut head = vec![];
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    assert!(head.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    stream
+}
+
+/// Reads the rest of the body in `chunk` sized reads, pausing after each.
+fn read_slowly(stream: &mut TcpStream, chunk: usize, pause: Duration) -> usize {
+    let mut buffer = vec![0; chunk];
+    let mut total = 0;
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => return total,
+            Ok(read) => total += read,
+        }
+        thread::sleep(pause);
+    }
+}
+
+#[test]
+fn slow_downloads_outlast_the_write_timeout() {
+    let dir = temp_dir("slow-download");
+    let length = 8 * 1024 * 1024;
+    fs::write(dir.join("large"), vec![b'x'; length]).unwrap();
+    let server = TestServer::spawn_with(|server| {
+        server.files(&dir).write_timeout(Duration::from_secs(1))
+    });
+
+    // Takes about three seconds, but the client never stops reading.
+    let mut stream = request_file(&server, "large");
+    let started = Instant::now();
+    let received = read_slowly(&mut stream, 64 * 1024, Duration::from_millis(25));
+    assert!(started.elapsed() > Duration::from_secs(1));
+    assert_eq!(received, length);
+}
+
+#[test]
+fn downloads_stalled_past_the_write_timeout_are_cut_off() {
+    let dir = temp_dir("stalled-download");
+    let length = 64 * 1024 * 1024;
+    fs::write(dir.join("large"), vec![b'x'; length]).unwrap();
+    let server = TestServer::spawn_with(|server| {
+        server.files(&dir).write_timeout(Duration::from_secs(1))
+    });
+
+    let mut stream = request_file(&server, "large");
+    thread::sleep(Duration::from_secs(2));
+    let received = read_slowly(&mut stream, 1024 * 1024, Duration::ZERO);
+    assert!(received < length, "got all {} bytes", received);
+}
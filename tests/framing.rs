@@ -0,0 +1,86 @@
+mod common;
+
+use std::fs;
+
+use pretty_assertions::assert_eq;
+
+use common::{temp_dir, TestServer};
+
+#[test]
+fn decodes_chunked_uploads() {
+    let dir = temp_dir("framing-chunked");
+    let server = TestServer::spawn_with(|server| server.files(&dir));
+
+    let (status, _) = server.upload(
+        "chunked",
+        "Transfer-Encoding: chunked\r\n",
+        "5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nChecksum: none\r\n\r\n",
+    );
+    assert_eq!(status, "HTTP/1.1 201 Created");
+    assert_eq!(fs::read_to_string(dir.join("chunked")).unwrap(), "hello, world");
+}
+
+#[test]
+fn rejects_ambiguous_framing() {
+    let dir = temp_dir("framing-ambiguous");
+    let server = TestServer::spawn_with(|server| server.files(&dir));
+    let chunked = "5\r\nhello\r\n0\r\n\r\n";
+
+    let cases = [
+        (
+            "Content-Length: 5\r\nTransfer-Encoding: chunked\r\n",
+            chunked,
+            "HTTP/1.1 400 Bad Request",
+            "both Content-Length and Transfer-Encoding are set\n",
+        ),
+        (
+            "Transfer-Encoding: gzip, chunked\r\n",
+            chunked,
+            "HTTP/1.1 501 Not Implemented",
+            "unsupported transfer encoding: gzip, chunked\n",
+        ),
+        (
+            "Transfer-Encoding: chunked, gzip\r\n",
+            chunked,
+            "HTTP/1.1 501 Not Implemented",
+            "unsupported transfer encoding: chunked, gzip\n",
+        ),
+        (
+            "Transfer-Encoding: chunked, chunked\r\n",
+            chunked,
+            "HTTP/1.1 400 Bad Request",
+            "chunked is applied more than once\n",
+        ),
+        (
+            "Transfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n",
+            chunked,
+            "HTTP/1.1 400 Bad Request",
+            "chunked is applied more than once\n",
+        ),
+        (
+            "Transfer-Encoding: chunked,\r\n",
+            chunked,
+            "HTTP/1.1 400 Bad Request",
+            "malformed header: invalid Transfer-Encoding: \"chunked,\"\n",
+        ),
+        (
+            "Transfer-Encoding: chunked\r\n",
+            "+5\r\nhello\r\n0\r\n\r\n",
+            "HTTP/1.1 400 Bad Request",
+            "malformed chunked body: invalid chunk size: \"+5\"\n",
+        ),
+        (
+            "Transfer-Encoding: chunked\r\n",
+            " 5\r\nhello\r\n0\r\n\r\n",
+            "HTTP/1.1 400 Bad Request",
+            "malformed chunked body: invalid chunk size: \" 5\"\n",
+        ),
+    ];
+    for (index, (framing, body, expected_status, expected_reason)) in cases.into_iter().enumerate() {
+        let name = format!("upload-{}", index);
+        let (status, reason) = server.upload(&name, framing, body);
+        assert_eq!(status, expected_status, "{}", framing);
+        assert_eq!(reason, expected_reason, "{}", framing);
+        assert!(!dir.join(name).exists(), "{}", framing);
+    }
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use pretty_assertions::assert_eq;
+
+use http_server_starter_rust::RequestLimits;
+
+use common::{temp_dir, TestServer};
+
+const LIMITS: RequestLimits = RequestLimits {
+    max_request_line: 64,
+    max_header_size: 64,
+    max_headers: 4,
+    max_body_size: 16,
+};
+
+/// A request line of exactly `length` bytes.
+fn request_line(length: usize) -> String {
+    let padding = length - "GET /echo/ HTTP/1.1".len();
+    format!("GET /echo/{} HTTP/1.1", "a".repeat(padding))
+}
+
+/// A header line of exactly `length` bytes.
+fn header_line(length: usize) -> String {
+    format!("X-Padding: {}", "a".repeat(length - "X-Padding: ".len()))
+}
+
+#[test]
+fn enforces_request_head_limits() {
+    let server = TestServer::spawn_with(|server| server.limits(LIMITS));
+    // Host and Connection are two of the four headers allowed.
+    let cases = [
+        (request_line(64), String::new(), "HTTP/1.1 200 OK"),
+        (request_line(65), String::new(), "HTTP/1.1 414 URI Too Long"),
+        (
+            request_line(20),
+            format!("{}\r\n", header_line(64)),
+            "HTTP/1.1 200 OK",
+        ),
+        (
+            request_line(20),
+            format!("{}\r\n", header_line(65)),
+            "HTTP/1.1 431 Request Header Fields Too Large",
+        ),
+        (
+            request_line(20),
+            "A: 1\r\nB: 2\r\n".to_string(),
+            "HTTP/1.1 200 OK",
+        ),
+        (
+            request_line(20),
+            "A: 1\r\nB: 2\r\nC: 3\r\n".to_string(),
+            "HTTP/1.1 431 Request Header Fields Too Large",
+        ),
+        // Folded lines count as lines of their own.
+        (
+            request_line(20),
+            "A: 1\r\n 2\r\n 3\r\n".to_string(),
+            "HTTP/1.1 431 Request Header Fields Too Large",
+        ),
+    ];
+
+    for (line, headers, expected) in cases {
+        let request = format!(
+            "{}\r\nHost: localhost\r\n{}Connection: close\r\n\r\n",
+            line, headers
+        );
+        assert_eq!(server.status_of(&request), expected, "{:?}", request);
+    }
+}
+
+#[test]
+fn enforces_body_limit() {
+    let dir = temp_dir("limits");
+    let server = TestServer::spawn_with(|server| server.files(&dir).limits(LIMITS));
+    let upload = |name: &str, framing: &str, body: &str| server.upload(name, framing, body).0;
+
+    let at_limit = "a".repeat(16);
+    assert_eq!(
+        upload("exact", "Content-Length: 16\r\n", &at_limit),
+        "HTTP/1.1 201 Created"
+    );
+    // Refused from the header alone, before any of the body is read.
+    assert_eq!(
+        upload("over", "Content-Length: 17\r\n", ""),
+        "HTTP/1.1 413 Payload Too Large"
+    );
+    assert_eq!(
+        upload("huge", "Content-Length: 99999999999999999999\r\n", ""),
+        "HTTP/1.1 400 Bad Request"
+    );
+    // Chunked bodies are counted as they arrive.
+    assert_eq!(
+        upload(
+            "chunked",
+            "Transfer-Encoding: chunked\r\n",
+            "10\r\naaaaaaaaaaaaaaaa\r\n1\r\na\r\n0\r\n\r\n"
+        ),
+        "HTTP/1.1 413 Payload Too Large"
+    );
+    assert!(!dir.join("over").exists());
+    assert!(!dir.join("chunked").exists());
+}
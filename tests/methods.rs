@@ -0,0 +1,65 @@
+mod common;
+
+use pretty_assertions::assert_eq;
+
+use common::TestServer;
+
+#[test]
+fn head_sends_get_headers_without_body() {
+    let server = TestServer::spawn();
+
+    let (head, rest) = server.exchange(
+        b"HEAD /echo/hello HTTP/1.1\r\nHost: localhost\r\n\r\n\
+          GET /echo/next HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+
+    // The GET response follows the HEAD headers right away.
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains("Content-Length: 5\r\n"));
+    let get = String::from_utf8(rest).unwrap();
+    assert!(get.starts_with("HTTP/1.1 200 OK\r\n"), "{}", get);
+    assert!(get.ends_with("\r\n\r\nnext"), "{}", get);
+}
+
+#[test]
+fn options_lists_allowed_methods() {
+    let server = TestServer::spawn();
+
+    let (response, _) = server.exchange(
+        b"OPTIONS /files/a HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+
+    assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+    let allow = response
+        .lines()
+        .find_map(|line| line.strip_prefix("Allow: "))
+        .unwrap();
+    assert_eq!(allow, "GET, HEAD, POST, OPTIONS");
+}
+
+#[test]
+fn unknown_method_is_not_implemented() {
+    let server = TestServer::spawn();
+
+    let (response, _) = server.exchange(
+        b"BREW / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+
+    assert!(response.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+}
+
+#[test]
+fn unregistered_method_is_not_allowed() {
+    let server = TestServer::spawn();
+
+    let (response, _) = server.exchange(
+        b"DELETE /files/a HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+
+    assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    let allow = response
+        .lines()
+        .find_map(|line| line.strip_prefix("Allow: "))
+        .unwrap();
+    assert_eq!(allow, "GET, HEAD, POST, OPTIONS");
+}
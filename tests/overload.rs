@@ -0,0 +1,44 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use common::TestServer;
+
+/// Sends a request that closes the connection and returns what comes back.
+fn get_root(stream: &mut TcpStream) -> String {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn connections_over_the_limit_get_503() {
+    let server = TestServer::spawn_with(|server| server.max_connections(1));
+    let mut held = server.connect();
+    // Make sure the first connection has its slot before the second comes.
+    held.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut buffer = [0; 1024];
+    assert!(held.read(&mut buffer).unwrap() > 0);
+
+    let mut rejected = server.connect();
+    let response = get_root(&mut rejected);
+    assert!(
+        response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+        "{}",
+        response
+    );
+    assert!(response.contains("\r\nRetry-After: 1\r\n"), "{}", response);
+    assert!(response.contains("\r\nConnection: close\r\n"), "{}", response);
+
+    // Once the first connection is gone, its slot is free again.
+    drop(held);
+    thread::sleep(Duration::from_millis(100));
+    let response = get_root(&mut server.connect());
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::io::{Read, Write};
+
+use pretty_assertions::assert_eq;
+
+use common::TestServer;
+
+#[test]
+fn reads_header_values_however_they_are_spaced() {
+    let server = TestServer::spawn();
+    let cases: [(&[u8], &str); 3] = [
+        (b"User-Agent:curl\r\n", "curl"),
+        (b"User-Agent:\tcurl/8.0 \t\r\n", "curl/8.0"),
+        // obs-fold, each folded line break becomes a single space.
+        (b"User-Agent: curl\r\n  8.0\r\n\tlinux\r\n", "curl 8.0 linux"),
+    ];
+
+    for (user_agent, expected) in cases {
+        let mut stream = server.connect();
+        stream
+            .write_all(b"GET /user-agent HTTP/1.1\r\nHost: localhost\r\n")
+            .unwrap();
+        stream.write_all(user_agent).unwrap();
+        stream.write_all(b"Connection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        assert_eq!(body, expected);
+    }
+}
+
+#[test]
+fn reads_a_request_arriving_byte_by_byte() {
+    let server = TestServer::spawn();
+    let mut stream = server.connect();
+    stream.set_nodelay(true).unwrap();
+
+    for byte in b"GET /echo/slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n" {
+        stream.write_all(&[*byte]).unwrap();
+    }
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("\r\n\r\nslow"), "{}", response);
+}
+
+#[test]
+fn decodes_the_path_and_leaves_the_query_out() {
+    let server = TestServer::spawn();
+    let mut stream = server.connect();
+
+    stream
+        .write_all(
+            b"GET /echo/hello%20world?x=1&x=2 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    assert_eq!(body, "hello world");
+}
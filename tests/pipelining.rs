@@ -0,0 +1,66 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use pretty_assertions::assert_eq;
+
+use common::TestServer;
+
+#[test]
+fn answers_pipelined_requests_in_order() {
+    let server = TestServer::spawn();
+    let mut stream = server.connect();
+
+    stream
+        .write_all(
+            b"GET /echo/first HTTP/1.1\r\nHost: localhost\r\n\r\n\
+              GET /echo/second HTTP/1.1\r\nHost: localhost\r\n\r\n\
+              GET /echo/third HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let bodies: Vec<&str> = response
+        .split("HTTP/1.1 ")
+        .skip(1)
+        .map(|part| part.rsplit("\r\n\r\n").next().unwrap())
+        .collect();
+    assert_eq!(bodies, vec!["first", "second", "third"]);
+}
+
+/// Reads from `stream` until what came in ends with `ending`.
+fn read_until_ends_with(stream: &mut TcpStream, ending: &str) -> String {
+    let mut response = vec![];
+    let mut buffer = [0; 1024];
+    while !response.ends_with(ending.as_bytes()) {
+        let read = stream.read(&mut buffer).expect("response didn't arrive in time");
+        assert!(read > 0, "connection closed early");
+        response.extend_from_slice(&buffer[..read]);
+    }
+    String::from_utf8(response).unwrap()
+}
+
+#[test]
+fn answers_right_away_when_a_stray_line_break_follows() {
+    let server = TestServer::spawn();
+    let mut stream = server.connect();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    // Some clients end a request with an extra CRLF, which is buffered as
+    // the start of a next request that never comes.
+    stream
+        .write_all(b"GET /echo/first HTTP/1.1\r\nHost: localhost\r\n\r\n\r\n")
+        .unwrap();
+    let response = read_until_ends_with(&mut stream, "\r\n\r\nfirst");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+
+    stream
+        .write_all(b"GET /echo/second HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let response = read_until_ends_with(&mut stream, "\r\n\r\nsecond");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+}
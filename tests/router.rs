@@ -0,0 +1,112 @@
+mod common;
+
+use std::io::{Read, Write};
+
+use pretty_assertions::assert_eq;
+
+use http_server_starter_rust::encoding::types::EncodedContent;
+use http_server_starter_rust::request::HttpRequestType;
+use http_server_starter_rust::response::HttpResponseType;
+use http_server_starter_rust::routing::{ResponseFuture, Stack};
+use http_server_starter_rust::{
+    Handler, HttpRequest, HttpResponse, HttpResponseBuilder, Middleware, Router,
+};
+
+use common::TestServer;
+
+fn text(body: String) -> HttpResponse {
+    HttpResponseBuilder::new(HttpResponseType::Ok)
+        .body(EncodedContent::from(body.into_bytes()))
+        .build()
+}
+
+fn router() -> Router {
+    let param = |request: &HttpRequest, name: &str| {
+        String::from(request.params.get(name).unwrap_or_default())
+    };
+    // Registered least specific first, so the order doesn't decide.
+    Router::new()
+        .route(HttpRequestType::Get, "/items/{*rest}", move |request: HttpRequest| async move {
+            text(format!("wildcard {}", param(&request, "rest")))
+        })
+        .route(HttpRequestType::Get, "/items/{name}", move |request: HttpRequest| async move {
+            text(format!("name {}", param(&request, "name")))
+        })
+        .route(HttpRequestType::Get, "/items/{id:int}", move |request: HttpRequest| async move {
+            text(format!("id {}", param(&request, "id")))
+        })
+        .route(HttpRequestType::Get, "/items/new", |_request: HttpRequest| async move {
+            text(String::from("new"))
+        })
+        .route(HttpRequestType::Get, "/search", |request: HttpRequest| async move {
+            text(request.query.get_all("q").collect::<Vec<_>>().join("|"))
+        })
+}
+
+/// Turns away requests without a token, checking it the way a lookup in
+/// another service would: asynchronously.
+struct RequireToken;
+
+impl Middleware for RequireToken {
+    fn handle<'a>(&'a self, request: HttpRequest, next: &'a dyn Handler) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            tokio::task::yield_now().await;
+            if request.header.get("token") != Some("secret") {
+                return HttpResponseBuilder::new(HttpResponseType::Unauthorized).build();
+            }
+            next.handle(request).await
+        })
+    }
+}
+
+/// Sends a GET for `target` with `headers` and returns the status line and
+/// body.
+fn get_with(server: &TestServer, target: &str, headers: &str) -> (String, String) {
+    let mut stream = server.connect();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n",
+        target, headers
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.lines().next().unwrap();
+    (String::from(status), String::from(body))
+}
+
+fn get(server: &TestServer, target: &str) -> (String, String) {
+    get_with(server, target, "")
+}
+
+#[test]
+fn routes_to_the_most_specific_pattern() {
+    let server = TestServer::serve(|server| server, router());
+
+    let cases = [
+        ("/items/new", "new"),
+        ("/items/42", "id 42"),
+        ("/items/4x2", "name 4x2"),
+        ("/items/caf%C3%A9", "name café"),
+        ("/items/a/b", "wildcard a/b"),
+        ("/search?q=a+b&other=c&q=%26", "a b|&"),
+    ];
+    for (target, expected) in cases {
+        let (status, body) = get(&server, target);
+        assert_eq!(status, "HTTP/1.1 200 OK", "GET {}", target);
+        assert_eq!(body, expected, "GET {}", target);
+    }
+    let (status, _) = get(&server, "/other");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+}
+
+#[test]
+fn middleware_can_answer_asynchronously() {
+    let server = TestServer::serve(|server| server, Stack::new(router()).with(RequireToken));
+
+    let (status, _) = get(&server, "/items/new");
+    assert_eq!(status, "HTTP/1.1 401 Unauthorized");
+    let (status, body) = get_with(&server, "/items/new", "Token: secret\r\n");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body, "new");
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use http_server_starter_rust::encoding::types::EncodedContent;
+use http_server_starter_rust::response::HttpResponseType;
+use http_server_starter_rust::{routing, HttpRequest, HttpResponseBuilder, Server};
+use pretty_assertions::assert_eq;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use common::{temp_dir, TestServer};
+
+#[test]
+fn finishes_in_progress_upload_on_shutdown() {
+    let dir = temp_dir("shutdown");
+    let mut server = TestServer::spawn_with(|server| server.files(&dir));
+    let mut stream = server.connect();
+
+    stream
+        .write_all(b"POST /files/upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nhello")
+        .unwrap();
+    thread::sleep(Duration::from_millis(200));
+    server.terminate();
+    thread::sleep(Duration::from_millis(200));
+
+    // The listener is gone, but the upload that already started goes through.
+    assert!(TcpStream::connect(server.address).is_err());
+    stream.write_all(b"world").unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 201 Created\r\n"), "{}", response);
+    assert!(response.contains("Connection: close\r\n"), "{}", response);
+    assert_eq!(fs::read_to_string(dir.join("upload")).unwrap(), "helloworld");
+
+    server.wait().unwrap();
+}
+
+#[tokio::test]
+async fn cuts_off_connections_left_after_drain_timeout() {
+    let server = Server::bind("127.0.0.1:0")
+        .unwrap()
+        .drain_timeout(Duration::from_millis(200));
+    let address = server.local_addr().unwrap();
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let serving = tokio::spawn(server.serve_with_shutdown(routing::service(), async move {
+        let _ = stopped.await;
+    }));
+
+    // A request that never finishes keeps its connection from draining.
+    let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: local").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    drop(stop);
+    serving.await.unwrap().unwrap();
+
+    // Once serving returns, the connection is gone even though the runtime
+    // lives on.
+    let mut response = vec![];
+    let read = tokio::time::timeout(Duration::from_secs(1), stream.read_to_end(&mut response))
+        .await
+        .expect("connection outlived the server");
+    assert!(read.is_err() || response.is_empty(), "{:?}", response);
+}
+
+#[test]
+fn answers_pipelined_request_buffered_when_shutdown_starts() {
+    // Slow enough for shutdown to start while the first request is handled.
+    let slow = |_request: HttpRequest| async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        HttpResponseBuilder::new(HttpResponseType::Ok)
+            .body(EncodedContent::from(b"done".to_vec()))
+            .build()
+    };
+    let mut server = TestServer::serve(|server| server, slow);
+    let mut stream = server.connect();
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    thread::sleep(Duration::from_millis(100));
+    server.terminate();
+
+    // The second request was already received, so it's answered before the
+    // connection closes.
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 2, "{}", response);
+    assert!(response.contains("Connection: close\r\n"), "{}", response);
+
+    server.wait().unwrap();
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::io::Cursor;
+
+use pretty_assertions::assert_eq;
+
+use http_server_starter_rust::request::HttpRequestType;
+use http_server_starter_rust::response::streaming::StreamingBody;
+use http_server_starter_rust::response::HttpResponseType;
+use http_server_starter_rust::{HttpRequest, HttpResponseBuilder, Router};
+
+use common::{dechunk, TestServer};
+
+/// Long enough to take several chunks.
+fn body() -> Vec<u8> {
+    (0..40_000).map(|index| b'a' + (index % 26) as u8).collect()
+}
+
+fn router() -> Router {
+    Router::new()
+        .route(HttpRequestType::Get, "/unknown", |_request: HttpRequest| async {
+            HttpResponseBuilder::new(HttpResponseType::Ok)
+                .stream(StreamingBody::new(Cursor::new(body()), None))
+                .build()
+        })
+        .route(HttpRequestType::Get, "/known", |_request: HttpRequest| async {
+            let length = body().len() as u64;
+            HttpResponseBuilder::new(HttpResponseType::Ok)
+                .stream(StreamingBody::new(Cursor::new(body()), Some(length)))
+                .build()
+        })
+}
+
+#[test]
+fn chunks_bodies_of_unknown_length() {
+    let server = TestServer::serve(|server| server, router());
+
+    let (head, body_bytes) = server.exchange(
+        "GET /unknown HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+
+    assert!(head.contains("\r\nTransfer-Encoding: chunked\r\n"), "{}", head);
+    assert!(!head.contains("Content-Length"), "{}", head);
+    assert_eq!(dechunk(&body_bytes), body());
+}
+
+#[test]
+fn sends_known_lengths_as_content_length() {
+    let server = TestServer::serve(|server| server, router());
+
+    let (head, body_bytes) = server.exchange(
+        "GET /known HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+
+    assert!(head.contains("\r\nContent-Length: 40000\r\n"), "{}", head);
+    assert!(!head.contains("Transfer-Encoding"), "{}", head);
+    assert_eq!(body_bytes, body());
+}
+
+#[test]
+fn ends_unknown_lengths_by_closing_for_http_1_0() {
+    let server = TestServer::serve(|server| server, router());
+
+    // Asking to keep the connection open can't be honoured, closing it is
+    // the only way to tell where the body ends.
+    let (head, body_bytes) = server.exchange(
+        "GET /unknown HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+    );
+
+    assert!(head.contains("\r\nConnection: close\r\n"), "{}", head);
+    assert!(!head.contains("Transfer-Encoding"), "{}", head);
+    assert!(!head.contains("Content-Length"), "{}", head);
+    assert_eq!(body_bytes, body());
+}
+
+#[test]
+fn head_sends_no_chunks() {
+    let server = TestServer::serve(|server| server, router());
+
+    let (head, body_bytes) = server.exchange(
+        "HEAD /unknown HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+
+    assert!(head.contains("\r\nTransfer-Encoding: chunked\r\n"), "{}", head);
+    assert_eq!(body_bytes, b"");
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{temp_dir, TestServer};
+
+fn spawn_server() -> TestServer {
+    TestServer::spawn_with(|server| {
+        server
+            .header_timeout(Duration::from_secs(1))
+            .body_timeout(Duration::from_secs(1))
+            .keep_alive_timeout(Duration::from_secs(1))
+    })
+}
+
+/// Reads until the server closes the connection, failing the test if it
+/// doesn't within a few seconds.
+fn read_until_closed(stream: &mut TcpStream) -> String {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut response = vec![];
+    stream
+        .read_to_end(&mut response)
+        .expect("connection wasn't closed in time");
+    String::from_utf8(response).unwrap()
+}
+
+#[test]
+fn incomplete_headers_get_408() {
+    let server = spawn_server();
+    let mut stream = server.connect();
+
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n").unwrap();
+    let started = Instant::now();
+    let response = read_until_closed(&mut stream);
+
+    assert!(started.elapsed() >= Duration::from_millis(900));
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{}", response);
+    assert!(response.contains("Connection: close\r\n"), "{}", response);
+}
+
+#[test]
+fn trickling_headers_do_not_extend_the_timeout() {
+    let server = spawn_server();
+    let mut stream = server.connect();
+    stream.set_nodelay(true).unwrap();
+
+    // A byte every 100 ms keeps the connection busy, but the headers as a
+    // whole still have to arrive within the timeout.
+    let started = Instant::now();
+    for byte in b"GET / HTTP/1.1\r\nX-Padding: aaaaaaaaaaaaaaaaaaaa" {
+        if stream.write_all(&[*byte]).is_err() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let response = read_until_closed(&mut stream);
+
+    assert!(started.elapsed() < Duration::from_secs(4));
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{}", response);
+}
+
+#[test]
+fn incomplete_body_closes_the_connection() {
+    let server = spawn_server();
+    let mut stream = server.connect();
+
+    stream
+        .write_all(b"POST /echo/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nhello")
+        .unwrap();
+    let response = read_until_closed(&mut stream);
+
+    // The client is mid-body, a response would be read as part of it.
+    assert_eq!(response, "");
+}
+
+#[test]
+fn slow_uploads_outlast_the_body_timeout() {
+    let dir = temp_dir("timeouts");
+    let server = TestServer::spawn_with(|server| {
+        server.files(&dir).body_timeout(Duration::from_secs(1))
+    });
+    let mut stream = server.connect();
+    stream.set_nodelay(true).unwrap();
+
+    // Every byte comes well within the timeout, though the body as a whole
+    // takes longer.
+    stream
+        .write_all(b"POST /files/slow HTTP/1.1\r\nHost: localhost\r\nContent-Length: 20\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let started = Instant::now();
+    for byte in b"slowly but steadily." {
+        stream.write_all(&[*byte]).unwrap();
+        thread::sleep(Duration::from_millis(100));
+    }
+    let response = read_until_closed(&mut stream);
+
+    assert!(started.elapsed() >= Duration::from_secs(2));
+    assert!(response.starts_with("HTTP/1.1 201 Created\r\n"), "{}", response);
+    assert_eq!(fs::read_to_string(dir.join("slow")).unwrap(), "slowly but steadily.");
+}
+
+#[test]
+fn idle_connections_are_closed_quietly() {
+    let server = spawn_server();
+    let mut stream = server.connect();
+
+    stream
+        .write_all(b"GET /echo/a HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let started = Instant::now();
+    let response = read_until_closed(&mut stream);
+
+    assert!(started.elapsed() >= Duration::from_millis(900));
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\na"), "{}", response);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+use common::{temp_dir, TestServer};
+
+/// Sends one request and returns the status code of the response.
+fn status_code(server: &TestServer, request: &str) -> u16 {
+    let status = server.status_of(request);
+    status.split(' ').nth(1).unwrap().parse().unwrap()
+}
+
+fn get(server: &TestServer, target: &str) -> u16 {
+    status_code(server, &format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        target
+    ))
+}
+
+fn post(server: &TestServer, target: &str) -> u16 {
+    status_code(server, &format!(
+        "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nConnection: close\r\n\r\nowned",
+        target
+    ))
+}
+
+/// Serves `<base>/served`, with a secret next to it and links pointing out.
+fn spawn_server(base: &Path) -> TestServer {
+    let served = base.join("served");
+    fs::create_dir_all(served.join("sub")).unwrap();
+    fs::write(served.join("sub").join("ok.txt"), "ok").unwrap();
+    fs::write(served.join("with space.txt"), "ok").unwrap();
+    fs::write(base.join("secret.txt"), "secret").unwrap();
+    symlink(base.join("secret.txt"), served.join("escape")).unwrap();
+    symlink(base, served.join("outside")).unwrap();
+    symlink(base.join("planted.txt"), served.join("dangling")).unwrap();
+    symlink(served.join("sub").join("ok.txt"), served.join("inside")).unwrap();
+    TestServer::spawn_with(|server| server.files(served))
+}
+
+#[test]
+fn files_stay_inside_the_served_directory() {
+    let base = temp_dir("traversal");
+    let server = spawn_server(&base);
+
+    let attacks = [
+        ("/files/../secret.txt", 403),
+        ("/files/sub/../../secret.txt", 403),
+        ("/files/%2e%2e/secret.txt", 403),
+        ("/files/%2E%2E%2Fsecret.txt", 403),
+        ("/files/..%2fsecret.txt", 403),
+        // Decoded once only, this is a file literally named "%2e%2e".
+        ("/files/%252e%252e/secret.txt", 404),
+        ("/files/escape", 403),
+        ("/files/outside/secret.txt", 403),
+        ("/files/dangling", 404),
+        ("/files/secret.txt%00.png", 400),
+        ("/files/%zz", 400),
+        ("/files/%ff", 400),
+    ];
+    for (target, expected) in attacks {
+        assert_eq!(get(&server, target), expected, "GET {}", target);
+    }
+    // A leading slash doesn't make the name absolute.
+    assert_eq!(get(&server, "/files//etc/passwd"), 404);
+
+    assert_eq!(get(&server, "/files/sub/ok.txt"), 200);
+    assert_eq!(get(&server, "/files/./sub//ok.txt"), 200);
+    assert_eq!(get(&server, "/files/with%20space.txt"), 200);
+    assert_eq!(get(&server, "/files/inside"), 200);
+
+    assert_eq!(post(&server, "/files/../planted.txt"), 403);
+    assert_eq!(post(&server, "/files/outside/planted.txt"), 403);
+    // Writing through the link would create the file outside.
+    assert_eq!(post(&server, "/files/dangling"), 409);
+    assert_eq!(post(&server, "/files/%00"), 400);
+    assert!(!base.join("planted.txt").exists());
+
+    assert_eq!(post(&server, "/files/sub/new.txt"), 201);
+    assert_eq!(
+        fs::read_to_string(base.join("served").join("sub").join("new.txt")).unwrap(),
+        "owned"
+    );
+}
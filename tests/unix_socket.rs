@@ -0,0 +1,79 @@
+mod common;
+
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+
+use pretty_assertions::assert_eq;
+
+use http_server_starter_rust::Server;
+
+use common::{temp_dir, TestServer};
+
+fn get_echo(socket: &Path) -> String {
+    let mut stream = UnixStream::connect(socket).unwrap();
+    stream
+        .write_all(b"GET /echo/unix HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn serves_over_a_unix_socket() {
+    let dir = temp_dir("unix-serve");
+    let socket = dir.join("server.sock");
+    let server = TestServer::spawn_with(|server| server.listen_unix(&socket, 0o660).unwrap());
+
+    let response = get_echo(&socket);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nunix"), "{}", response);
+
+    let metadata = fs::symlink_metadata(&socket).unwrap();
+    assert!(metadata.file_type().is_socket());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o660);
+
+    // The socket file goes away with the server.
+    server.wait().unwrap();
+    assert!(!socket.exists());
+}
+
+#[test]
+fn replaces_a_stale_socket() {
+    let dir = temp_dir("unix-stale");
+    let socket = dir.join("server.sock");
+    // Dropping the listener leaves its file behind with nobody listening.
+    drop(UnixListener::bind(&socket).unwrap());
+    assert!(socket.exists());
+
+    let _server = TestServer::spawn_with(|server| server.listen_unix(&socket, 0o600).unwrap());
+
+    let response = get_echo(&socket);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+}
+
+#[test]
+fn refuses_a_socket_another_server_listens_on() {
+    let dir = temp_dir("unix-in-use");
+    let socket = dir.join("server.sock");
+    let _listener = UnixListener::bind(&socket).unwrap();
+
+    let error = Server::new().listen_unix(&socket, 0o600).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::AddrInUse);
+    // The other server's socket is left alone.
+    assert!(UnixStream::connect(&socket).is_ok());
+}
+
+#[test]
+fn refuses_a_path_that_is_not_a_socket() {
+    let dir = temp_dir("unix-not-socket");
+    let path = dir.join("server.sock");
+    fs::write(&path, "keep me").unwrap();
+
+    let error = Server::new().listen_unix(&path, 0o600).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+}